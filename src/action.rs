@@ -0,0 +1,394 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use bevy::{
+    input::{
+        gamepad::{GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType},
+        Axis, InputSystem,
+    },
+    math::Vec3Swizzles,
+    prelude::*,
+    render::camera::OrthographicProjection,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gamepad::{stick_direction, ActiveGamepadRes, GamepadLabel},
+    player::Cannon,
+    window::window_to_world,
+    MainCamera,
+};
+
+///Maps the raw input devices to `Action`s. Gameplay systems only read `ActionsRes`.
+pub struct ActionPlugin;
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BindingsRes>()
+            .insert_resource(ActionsRes::default())
+            .insert_resource(RebindRes::default())
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_actions_system
+                    .label(ActionLabel::Update)
+                    .after(InputSystem)
+                    .after(GamepadLabel::Connection),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                rebind_system.after(ActionLabel::Update),
+            );
+    }
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub enum ActionLabel {
+    Update,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    ///Pointer button, clicks positions and fires on the board
+    Select,
+    ///Secondary pointer button for context actions on clickables
+    Context,
+    Fire,
+    AimLeft,
+    AimRight,
+    ///Slows down `AimLeft` and `AimRight`
+    FineAim,
+    MoveLeft,
+    MoveRight,
+    ///Moves to the column with the number, counted from the left
+    Column(u8),
+    Pause,
+    Undo,
+}
+
+impl Action {
+    ///Actions listed in the settings screen
+    pub const REBINDABLE: [Action; 10] = [
+        Action::Fire,
+        Action::AimLeft,
+        Action::AimRight,
+        Action::FineAim,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Pause,
+        Action::Undo,
+        Action::Select,
+        Action::Context,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    fn is_same_device(self, other: Binding) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Mouse(button) => write!(f, "Mouse {:?}", button),
+            Binding::Gamepad(button) => write!(f, "Pad {:?}", button),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BindingsRes(pub BTreeMap<Action, Vec<Binding>>);
+
+impl Default for BindingsRes {
+    fn default() -> Self {
+        let mut bindings = BTreeMap::new();
+        bindings.insert(Action::Select, vec![Binding::Mouse(MouseButton::Left)]);
+        bindings.insert(Action::Context, vec![Binding::Mouse(MouseButton::Right)]);
+        bindings.insert(
+            Action::Fire,
+            vec![
+                Binding::Key(KeyCode::Space),
+                Binding::Gamepad(GamepadButtonType::RightTrigger2),
+            ],
+        );
+        bindings.insert(Action::AimLeft, vec![Binding::Key(KeyCode::A)]);
+        bindings.insert(Action::AimRight, vec![Binding::Key(KeyCode::D)]);
+        bindings.insert(Action::FineAim, vec![Binding::Key(KeyCode::LShift)]);
+        bindings.insert(
+            Action::MoveLeft,
+            vec![
+                Binding::Key(KeyCode::Left),
+                Binding::Gamepad(GamepadButtonType::DPadLeft),
+            ],
+        );
+        bindings.insert(
+            Action::MoveRight,
+            vec![
+                Binding::Key(KeyCode::Right),
+                Binding::Gamepad(GamepadButtonType::DPadRight),
+            ],
+        );
+        let column_keys = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+        ];
+        for (i, key) in column_keys.iter().enumerate() {
+            bindings.insert(Action::Column(i as u8 + 1), vec![Binding::Key(*key)]);
+        }
+        bindings.insert(
+            Action::Pause,
+            vec![
+                Binding::Key(KeyCode::Escape),
+                Binding::Gamepad(GamepadButtonType::Start),
+            ],
+        );
+        bindings.insert(Action::Undo, vec![Binding::Key(KeyCode::U)]);
+        Self(bindings)
+    }
+}
+
+impl BindingsRes {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    ///Replaces the binding of the action on the same device, the other devices keep their bindings
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.0.entry(action).or_default();
+        bindings.retain(|other| !other.is_same_device(binding));
+        bindings.push(binding);
+    }
+
+    pub fn label(&self, action: Action) -> String {
+        let bindings = self
+            .get(action)
+            .iter()
+            .map(Binding::to_string)
+            .collect::<Vec<_>>();
+        format!("{:?}: {}", action, bindings.join(", "))
+    }
+}
+
+///Action waiting for its new binding, the next pressed button is used
+#[derive(Default)]
+pub struct RebindRes(pub Option<Action>);
+
+#[derive(Default)]
+pub struct ActionsRes {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    ///Direction from the cannon to the mouse cursor or of the gamepad stick, if one moved this frame
+    pub aim: Option<Vec2>,
+}
+
+impl ActionsRes {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    pub fn press(&mut self, action: Action) {
+        if self.pressed.insert(action) {
+            self.just_pressed.insert(action);
+        }
+    }
+
+    pub fn release(&mut self, action: Action) {
+        if self.pressed.remove(&action) {
+            self.just_released.insert(action);
+        }
+    }
+
+    ///Removes the just pressed and released actions of the last frame
+    pub fn update(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.aim = None;
+    }
+
+    pub fn reset(&mut self) {
+        self.update();
+        self.pressed.clear();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_actions_system(
+    mut actions: ResMut<ActionsRes>,
+    bindings: Res<BindingsRes>,
+    rebind: Res<RebindRes>,
+    active_gamepad: Res<ActiveGamepadRes>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    query_cannon: Query<&GlobalTransform, With<Cannon>>,
+    query_camera: Query<(&GlobalTransform, &OrthographicProjection), With<MainCamera>>,
+) {
+    //Buttons pressed for a new binding do not trigger their old action
+    if rebind.0.is_some() {
+        actions.reset();
+        return;
+    }
+    actions.update();
+
+    for (action, action_bindings) in bindings.0.iter() {
+        let pressed = action_bindings.iter().any(|binding| match *binding {
+            Binding::Key(key) => keys.pressed(key),
+            Binding::Mouse(button) => mouse_buttons.pressed(button),
+            Binding::Gamepad(button) => active_gamepad.0.map_or(false, |gamepad| {
+                gamepad_buttons.pressed(GamepadButton(gamepad, button))
+            }),
+        });
+        if pressed {
+            actions.press(*action);
+        } else {
+            actions.release(*action);
+        }
+    }
+
+    if let (Some(cursor_moved), Ok(global_transform), Ok((camera_transform, projection))) = (
+        cursor_moved_events.iter().last(),
+        query_cannon.get_single(),
+        query_camera.get_single(),
+    ) {
+        let cursor_pos = window_to_world(cursor_moved.position, camera_transform, projection);
+        actions.aim = Some(cursor_pos - global_transform.translation.xy());
+    }
+    if let Some(gamepad) = active_gamepad.0 {
+        let stick = Vec2::new(
+            axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX))
+                .unwrap_or(0.0),
+            axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickY))
+                .unwrap_or(0.0),
+        );
+        if let Some(direction) = stick_direction(stick) {
+            actions.aim = Some(direction);
+        }
+    }
+}
+
+fn rebind_system(
+    mut rebind: ResMut<RebindRes>,
+    mut bindings: ResMut<BindingsRes>,
+    active_gamepad: Res<ActiveGamepadRes>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    let action = match rebind.0 {
+        Some(action) => action,
+        None => return,
+    };
+
+    let binding = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .find(|button| Some(button.0) == active_gamepad.0)
+                .map(|button| Binding::Gamepad(button.1))
+        });
+
+    if let Some(binding) = binding {
+        info!("Bind {:?} to {}", action, binding);
+        bindings.rebind(action, binding);
+        rebind.0 = None;
+    }
+}
+
+#[test]
+fn test_rebind() {
+    let mut bindings = BindingsRes::default();
+    bindings.rebind(Action::Fire, Binding::Key(KeyCode::Return));
+    assert_eq!(
+        bindings.get(Action::Fire),
+        &[
+            Binding::Gamepad(GamepadButtonType::RightTrigger2),
+            Binding::Key(KeyCode::Return)
+        ]
+    );
+}
+
+#[test]
+fn test_gamepad_actions() {
+    use crate::{
+        gamepad::{gamepad_connection_system, GamepadLabel},
+        player::aim_angle,
+    };
+    use bevy::{
+        app::Events,
+        ecs::schedule::Stage,
+        input::gamepad::{Gamepad, GamepadEvent, GamepadEventType},
+    };
+
+    let gamepad = Gamepad(0);
+    let mut world = World::default();
+    world.insert_resource(ActionsRes::default());
+    world.insert_resource(BindingsRes::default());
+    world.insert_resource(RebindRes::default());
+    world.insert_resource(ActiveGamepadRes::default());
+    world.insert_resource(Input::<KeyCode>::default());
+    world.insert_resource(Input::<MouseButton>::default());
+    world.insert_resource(Input::<GamepadButton>::default());
+    world.insert_resource(Axis::<GamepadAxis>::default());
+    world.insert_resource(Events::<CursorMoved>::default());
+    world.insert_resource(Events::<GamepadEvent>::default());
+
+    world
+        .get_resource_mut::<Events<GamepadEvent>>()
+        .unwrap()
+        .send(GamepadEvent(gamepad, GamepadEventType::Connected));
+    {
+        let mut axes = world.get_resource_mut::<Axis<GamepadAxis>>().unwrap();
+        axes.set(GamepadAxis(gamepad, GamepadAxisType::LeftStickX), -1.0);
+        axes.set(GamepadAxis(gamepad, GamepadAxisType::LeftStickY), 1.0);
+    }
+    world
+        .get_resource_mut::<Input<GamepadButton>>()
+        .unwrap()
+        .press(GamepadButton(gamepad, GamepadButtonType::RightTrigger2));
+
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(gamepad_connection_system.label(GamepadLabel::Connection));
+    stage.add_system(update_actions_system.after(GamepadLabel::Connection));
+    stage.run(&mut world);
+
+    let actions = world.get_resource::<ActionsRes>().unwrap();
+    assert!(actions.just_pressed(Action::Fire));
+    let angle = actions.aim.and_then(|aim| aim_angle(aim, false)).unwrap();
+    assert!((angle - std::f32::consts::PI / 4.0).abs() < 1e-5);
+}
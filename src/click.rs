@@ -1,142 +1,391 @@
+use std::cmp::Ordering;
+
 use bevy::{math::Vec3Swizzles, prelude::*, render::camera::OrthographicProjection};
 
-use crate::MainCamera;
+use crate::{
+    action::{Action, ActionsRes},
+    collider2d::{polygon_contains, Collider},
+    window::{window_to_world, BoardConfigRes},
+    MainCamera,
+};
+
+///Cursor distance in world units, before a pressed `Draggable` starts to drag
+pub const DRAG_DISTANCE: f32 = 5.0;
 
 pub struct ClickablePlugin;
 impl Plugin for ClickablePlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::new()
-                .label(ClickExecutionLabel::Main)
-                .with_system(
-                    remove_clicked_system
-                        .system()
-                        .label(ClickExecutionLabel::RemoveClicked),
-                )
-                .with_system(hover_2d_system.system().label(ClickExecutionLabel::Hover))
-                .with_system(
-                    press_down_system
-                        .system()
-                        .label(ClickExecutionLabel::PressDown)
-                        .after(ClickExecutionLabel::RemoveClicked)
-                        .after(ClickExecutionLabel::Hover),
-                )
-                .with_system(release_system.system().label(ClickExecutionLabel::Release))
-                .with_system(
-                    remove_selected_system
-                        .system()
-                        .label(ClickExecutionLabel::RemoveSelected),
-                ),
-        );
+        app.add_event::<HoverEnter>()
+            .add_event::<HoverExit>()
+            .add_event::<PressEvent>()
+            .add_event::<ClickEvent>()
+            .add_event::<DragStart>()
+            .add_event::<DragMove>()
+            .add_event::<DragEnd>()
+            .insert_resource(CursorPositionRes::default())
+            .add_system_set(
+                SystemSet::new()
+                    .label(ClickExecutionLabel::Main)
+                    .with_system(
+                        cursor_position_system
+                            .system()
+                            .label(ClickExecutionLabel::Cursor),
+                    )
+                    .with_system(
+                        remove_clicked_system
+                            .system()
+                            .label(ClickExecutionLabel::RemoveClicked),
+                    )
+                    .with_system(
+                        hover_2d_system
+                            .system()
+                            .label(ClickExecutionLabel::Hover)
+                            .after(ClickExecutionLabel::Cursor),
+                    )
+                    .with_system(
+                        press_down_system
+                            .system()
+                            .label(ClickExecutionLabel::PressDown)
+                            .after(ClickExecutionLabel::RemoveClicked)
+                            .after(ClickExecutionLabel::Hover),
+                    )
+                    .with_system(release_system.system().label(ClickExecutionLabel::Release))
+                    .with_system(
+                        drag_system
+                            .system()
+                            .label(ClickExecutionLabel::Drag)
+                            .after(ClickExecutionLabel::PressDown),
+                    )
+                    .with_system(
+                        remove_selected_system
+                            .system()
+                            .label(ClickExecutionLabel::RemoveSelected),
+                    ),
+            );
     }
 }
 
-//TODO Button Type
 #[derive(Component)]
 pub struct Clickable {
-    pub size: Vec2,
+    pub shape: ClickShape,
     pub active: bool,
+    ///Pointer actions, that press and click it
+    pub buttons: Vec<Action>,
+}
+
+impl Clickable {
+    ///Active clickable for `Action::Select`
+    pub fn new(shape: ClickShape) -> Self {
+        Self {
+            shape,
+            active: true,
+            buttons: vec![Action::Select],
+        }
+    }
+}
+
+///Area of a `Clickable` in its local coordinates, centered on its transform
+pub enum ClickShape {
+    Rect(Vec2),
+    Circle(f32),
+    Polygon(Vec<Vec2>),
+}
+
+impl ClickShape {
+    pub fn contains(&self, local: Vec2) -> bool {
+        match self {
+            ClickShape::Rect(size) => local.abs().cmple(*size / 2.0).all(),
+            ClickShape::Circle(radius) => local.length_squared() <= radius * radius,
+            ClickShape::Polygon(points) => polygon_contains(points, local),
+        }
+    }
+}
+
+impl From<&Collider> for ClickShape {
+    fn from(collider: &Collider) -> Self {
+        ClickShape::Polygon(collider.points().to_vec())
+    }
+}
+
+///Can be dragged while the select button is pressed on it. The drag events tell where to,
+///the entity is not moved by the click module.
+#[derive(Component, Default)]
+pub struct Draggable {
+    ///Drag positions are snapped to the centers of the board cells
+    pub snap_to_grid: bool,
+}
+
+///A `Draggable` is pressed, it drags after the cursor moved `DRAG_DISTANCE`
+#[derive(Component)]
+pub struct Dragging {
+    ///Cursor position, when it was pressed
+    cursor_start: Vec2,
+    ///Position of the draggable, when it was pressed
+    start: Vec2,
+    ///Position sent with the last drag event
+    position: Vec2,
+    started: bool,
+}
+
+impl Dragging {
+    ///Where the draggable is dragged to by the cursor at `cursor`
+    fn target(&self, cursor: Vec2, snap_to_grid: bool, board: &BoardConfigRes) -> Vec2 {
+        let target = self.start + cursor - self.cursor_start;
+        if snap_to_grid {
+            board.snap_to_cell(target)
+        } else {
+            target
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct Hovered;
 
+///Pressed with `button`, until it is released
 #[derive(Component)]
-pub struct Selected;
+pub struct Selected {
+    pub button: Action,
+}
 
 ///Click event element that stays until next klick, ore removed
 #[derive(Component)]
-pub struct Clicked;
+pub struct Clicked {
+    pub button: Action,
+}
+
+///The cursor entered the clickable, it is `Hovered` now
+pub struct HoverEnter {
+    pub entity: Entity,
+}
+
+///The cursor left the clickable or another one blocks it
+pub struct HoverExit {
+    pub entity: Entity,
+}
+
+///The button was pressed on the clickable, it is `Selected` now
+pub struct PressEvent {
+    pub entity: Entity,
+    pub button: Action,
+}
+
+///The button was pressed and released on the clickable.
+///Sent as well for clicks by touch, keyboard or replay, which do not insert `Clicked`.
+pub struct ClickEvent {
+    pub entity: Entity,
+    pub button: Action,
+}
+
+///The draggable started to drag at `position`
+pub struct DragStart {
+    pub entity: Entity,
+    pub position: Vec2,
+}
+
+///The draggable was dragged by `delta` to `position`
+pub struct DragMove {
+    pub entity: Entity,
+    pub delta: Vec2,
+    pub position: Vec2,
+}
+
+///The draggable was dropped at `position`, `delta` is the whole distance of the drag
+pub struct DragEnd {
+    pub entity: Entity,
+    pub delta: Vec2,
+    pub position: Vec2,
+}
+
+///Cursor position in world coordinates, `None` before it entered the window
+#[derive(Default)]
+pub struct CursorPositionRes(pub Option<Vec2>);
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
 enum ClickExecutionLabel {
     Main,
+    Cursor,
     Hover,
+    Drag,
     RemoveClicked,
     PressDown,
     Release,
     RemoveSelected,
 }
 
-//FIXME Multiple Hovered components could be created
-fn hover_2d_system(
-    mut commands: Commands,
-    query: Query<(
-        Entity,
-        &GlobalTransform,
-        &Transform,
-        &Clickable,
-        Option<&Hovered>,
-    )>,
+///Whether `position` in world coordinates is inside the clickable, rotation and scale included
+pub fn contains(clickable: &Clickable, global_transform: &GlobalTransform, position: Vec2) -> bool {
+    let local = global_transform
+        .compute_matrix()
+        .inverse()
+        .transform_point3(position.extend(global_transform.translation.z))
+        .xy();
+    clickable.shape.contains(local)
+}
+
+///The active clickable at `position` with the highest z, it blocks all clickables below
+pub fn topmost_at<'a>(
+    clickables: impl Iterator<Item = (Entity, &'a GlobalTransform, &'a Clickable)>,
+    position: Vec2,
+) -> Option<Entity> {
+    clickables
+        .filter(|(_, global_transform, clickable)| {
+            clickable.active && contains(clickable, global_transform, position)
+        })
+        //A NaN z counts as equal instead of panicking
+        .max_by(|(_, a, _), (_, b, _)| {
+            a.translation
+                .z
+                .partial_cmp(&b.translation.z)
+                .unwrap_or(Ordering::Equal)
+        })
+        .map(|(entity, ..)| entity)
+}
+
+fn cursor_position_system(
+    mut cursor_position: ResMut<CursorPositionRes>,
+    mut window_position: Local<Option<Vec2>>,
     query_camera: Query<(&GlobalTransform, &OrthographicProjection), With<MainCamera>>,
     mut cursor_moved_event: EventReader<CursorMoved>,
 ) {
-    if let Some(cursor_moved) = cursor_moved_event.iter().next() {
-        if let Ok((camera_transform, orthographic_projection)) = query_camera.get_single() {
-            let cursor_pos = cursor_moved.position
-                + Vec2::new(orthographic_projection.left, orthographic_projection.bottom)
-                + camera_transform.translation.xy();
-
-            //FIXME Rotation and Scale not implemented
-            //FIXME No blocking. Solution: Order query by z
-            let mut element_found = false;
-            let mut query_order = query.iter().collect::<Vec<_>>();
-            query_order.sort_by(|(_, a_global, a_local, ..), (_, b_global, b_local, ..)| {
-                let a = a_global.translation.z + a_local.translation.z;
-                let b = b_global.translation.z + b_local.translation.z;
-                a.partial_cmp(&b).unwrap()
-            });
-            for (entity, global_transform, _, clickable, o_hovered) in query_order.into_iter() {
-                let (x, y, _): (f32, f32, _) = global_transform.translation.into();
-                let (width, height) = clickable.size.into();
-                let rect = (
-                    x - width / 2.0,
-                    y - height / 2.0,
-                    x + width / 2.0,
-                    y + height / 2.0,
-                );
-
-                if !element_found
-                    && clickable.active
-                    && cursor_pos.x >= rect.0
-                    && cursor_pos.x <= rect.2
-                    && cursor_pos.y >= rect.1
-                    && cursor_pos.y <= rect.3
-                {
-                    if !o_hovered.is_some() {
-                        commands.entity(entity).insert(Hovered);
-                        element_found = true;
-                    }
-                } else {
-                    if o_hovered.is_some() {
-                        commands.entity(entity).remove::<Hovered>();
-                    }
-                }
+    if let Some(cursor_moved) = cursor_moved_event.iter().last() {
+        *window_position = Some(cursor_moved.position);
+    }
+    //The camera can move, so the world position is updated every frame
+    cursor_position.0 = query_camera.get_single().ok().zip(*window_position).map(
+        |((camera_transform, orthographic_projection), position)| {
+            window_to_world(position, camera_transform, orthographic_projection)
+        },
+    );
+}
+
+///Keeps `Hovered` on exactly the topmost clickable under the cursor
+fn hover_2d_system(
+    mut commands: Commands,
+    mut hover_enter_events: EventWriter<HoverEnter>,
+    mut hover_exit_events: EventWriter<HoverExit>,
+    cursor_position: Res<CursorPositionRes>,
+    query: Query<(Entity, &GlobalTransform, &Clickable, Option<&Hovered>)>,
+) {
+    //Clickables move and change their state, so the hover is updated every frame
+    let hovered = cursor_position.0.and_then(|cursor_pos| {
+        topmost_at(
+            query
+                .iter()
+                .map(|(entity, global_transform, clickable, _)| {
+                    (entity, global_transform, clickable)
+                }),
+            cursor_pos,
+        )
+    });
+
+    for (entity, _, _, o_hovered) in query.iter() {
+        if Some(entity) == hovered {
+            if o_hovered.is_none() {
+                commands.entity(entity).insert(Hovered);
+                hover_enter_events.send(HoverEnter { entity });
             }
+        } else if o_hovered.is_some() {
+            commands.entity(entity).remove::<Hovered>();
+            hover_exit_events.send(HoverExit { entity });
         }
     }
 }
 
 fn press_down_system(
     mut commands: Commands,
+    mut press_events: EventWriter<PressEvent>,
     query: Query<(Entity, &Clickable), With<Hovered>>,
-    mouse_buttons: Res<Input<MouseButton>>,
+    actions: Res<ActionsRes>,
 ) {
-    for (entity, _) in query.iter() {
-        if mouse_buttons.just_pressed(MouseButton::Left) {
-            commands.entity(entity).insert(Selected);
+    for (entity, clickable) in query.iter() {
+        if let Some(&button) = clickable
+            .buttons
+            .iter()
+            .find(|button| actions.just_pressed(**button))
+        {
+            commands.entity(entity).insert(Selected { button });
+            press_events.send(PressEvent { entity, button });
         }
     }
 }
 
 fn release_system(
     mut commands: Commands,
-    query: Query<(Entity, &Clickable, &Selected), With<Hovered>>,
-    mouse_buttons: Res<Input<MouseButton>>,
+    mut click_events: EventWriter<ClickEvent>,
+    query: Query<(Entity, &Clickable, &Selected, Option<&Dragging>), With<Hovered>>,
+    actions: Res<ActionsRes>,
 ) {
-    for (entity, _, _) in query.iter() {
-        if mouse_buttons.just_released(MouseButton::Left) {
-            commands.entity(entity).insert(Clicked);
+    for (entity, _, selected, o_dragging) in query.iter() {
+        //Dropping a draggable is no click
+        if o_dragging.map_or(false, |dragging| dragging.started) {
+            continue;
+        }
+        if actions.just_released(selected.button) {
+            let button = selected.button;
+            commands.entity(entity).insert(Clicked { button });
+            click_events.send(ClickEvent { entity, button });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drag_system(
+    mut commands: Commands,
+    mut press_events: EventReader<PressEvent>,
+    mut drag_start_events: EventWriter<DragStart>,
+    mut drag_move_events: EventWriter<DragMove>,
+    mut drag_end_events: EventWriter<DragEnd>,
+    cursor_position: Res<CursorPositionRes>,
+    actions: Res<ActionsRes>,
+    board: Res<BoardConfigRes>,
+    query_pressed: Query<&GlobalTransform, With<Draggable>>,
+    mut query: Query<(Entity, &Draggable, &mut Dragging)>,
+) {
+    for (entity, draggable, mut dragging) in query.iter_mut() {
+        let cursor = cursor_position.0.unwrap_or(dragging.cursor_start);
+        let target = dragging.target(cursor, draggable.snap_to_grid, &board);
+        if !dragging.started && cursor.distance(dragging.cursor_start) >= DRAG_DISTANCE {
+            dragging.started = true;
+            drag_start_events.send(DragStart {
+                entity,
+                position: dragging.position,
+            });
+        }
+        if dragging.started && target != dragging.position {
+            drag_move_events.send(DragMove {
+                entity,
+                delta: target - dragging.position,
+                position: target,
+            });
+            dragging.position = target;
+        }
+        if actions.just_released(Action::Select) {
+            if dragging.started {
+                drag_end_events.send(DragEnd {
+                    entity,
+                    delta: dragging.position - dragging.start,
+                    position: dragging.position,
+                });
+            }
+            commands.entity(entity).remove::<Dragging>();
+        }
+    }
+
+    if let Some(cursor) = cursor_position.0 {
+        for event in press_events
+            .iter()
+            .filter(|event| event.button == Action::Select)
+        {
+            if let Ok(global_transform) = query_pressed.get(event.entity) {
+                let start = global_transform.translation.xy();
+                commands.entity(event.entity).insert(Dragging {
+                    cursor_start: cursor,
+                    start,
+                    position: start,
+                    started: false,
+                });
+            }
         }
     }
 }
@@ -144,10 +393,10 @@ fn release_system(
 fn remove_selected_system(
     mut commands: Commands,
     query: Query<(Entity, &Clickable, &Selected)>,
-    mouse_buttons: Res<Input<MouseButton>>,
+    actions: Res<ActionsRes>,
 ) {
-    for (entity, _, _) in query.iter() {
-        if mouse_buttons.just_released(MouseButton::Left) {
+    for (entity, _, selected) in query.iter() {
+        if actions.just_released(selected.button) {
             commands.entity(entity).remove::<Selected>();
         }
     }
@@ -157,11 +406,94 @@ fn remove_selected_system(
 fn remove_clicked_system(
     mut commands: Commands,
     query: Query<(Entity, &Clickable), With<Clicked>>,
-    mouse_buttons: Res<Input<MouseButton>>,
+    actions: Res<ActionsRes>,
 ) {
-    for (entity, _) in query.iter() {
-        if mouse_buttons.just_pressed(MouseButton::Left) {
+    for (entity, clickable) in query.iter() {
+        if clickable
+            .buttons
+            .iter()
+            .any(|button| actions.just_pressed(*button))
+        {
             commands.entity(entity).remove::<Clicked>();
         }
     }
 }
+
+#[test]
+fn test_contains_rotated_and_scaled() {
+    let clickable = Clickable::new(ClickShape::Rect(Vec2::new(4.0, 2.0)));
+    let transform = GlobalTransform {
+        translation: Vec3::new(10.0, 0.0, 0.0),
+        rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+        scale: Vec3::new(2.0, 2.0, 1.0),
+    };
+    //Rotated by 90°, the long side points up and is scaled to 8
+    assert!(contains(&clickable, &transform, Vec2::new(10.0, 3.5)));
+    assert!(!contains(&clickable, &transform, Vec2::new(13.5, 0.0)));
+    assert!(contains(&clickable, &transform, Vec2::new(11.5, -3.5)));
+}
+
+#[test]
+fn test_topmost_at() {
+    let clickable = Clickable::new(ClickShape::Rect(Vec2::new(2.0, 2.0)));
+    let inactive = Clickable {
+        active: false,
+        ..Clickable::new(ClickShape::Rect(Vec2::new(2.0, 2.0)))
+    };
+    let bottom = GlobalTransform::from_xyz(0.0, 0.0, 0.0);
+    let top = GlobalTransform::from_xyz(0.5, 0.0, 1.0);
+    let above = GlobalTransform::from_xyz(0.0, 0.0, 2.0);
+    let entities = [Entity::new(0), Entity::new(1), Entity::new(2)];
+    let clickables = [
+        (entities[0], &bottom, &clickable),
+        (entities[1], &top, &clickable),
+        (entities[2], &above, &inactive),
+    ];
+
+    assert_eq!(
+        topmost_at(clickables.iter().copied(), Vec2::ZERO),
+        Some(entities[1])
+    );
+    assert_eq!(
+        topmost_at(clickables.iter().copied(), Vec2::new(-0.8, 0.0)),
+        Some(entities[0])
+    );
+    assert_eq!(
+        topmost_at(clickables.iter().copied(), Vec2::new(5.0, 0.0)),
+        None
+    );
+}
+
+#[test]
+fn test_drag_target() {
+    let board = BoardConfigRes::default();
+    let dragging = Dragging {
+        cursor_start: Vec2::new(3.0, 4.0),
+        start: Vec2::new(20.0, 20.0),
+        position: Vec2::new(20.0, 20.0),
+        started: true,
+    };
+    assert_eq!(
+        dragging.target(Vec2::new(40.0, 4.0), false, &board),
+        Vec2::new(57.0, 20.0)
+    );
+    //Board cells are 40 wide with a center at x 40
+    assert_eq!(
+        dragging.target(Vec2::new(40.0, 4.0), true, &board),
+        Vec2::new(40.0, 20.0)
+    );
+}
+
+#[test]
+fn test_contains_shapes() {
+    use crate::collider2d::Triangle;
+
+    let transform = GlobalTransform::from_xyz(10.0, 10.0, 0.0);
+    let circle = Clickable::new(ClickShape::Circle(2.0));
+    assert!(contains(&circle, &transform, Vec2::new(11.0, 11.0)));
+    assert!(!contains(&circle, &transform, Vec2::new(11.8, 11.8)));
+
+    let triangle = Clickable::new((&Triangle::new_collider(Vec2::new(4.0, 4.0))).into());
+    assert!(contains(&triangle, &transform, Vec2::new(11.0, 9.0)));
+    assert!(!contains(&triangle, &transform, Vec2::new(9.0, 11.0)));
+}
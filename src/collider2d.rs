@@ -11,6 +11,16 @@ impl Collider {
         Collider(vec)
     }
 
+    ///Corners of the polyline in local coordinates
+    pub fn points(&self) -> &[Vec2] {
+        &self.0
+    }
+
+    ///Whether `point` in local coordinates is inside the polyline, closed to a polygon
+    pub fn contains_point(&self, point: Vec2) -> bool {
+        polygon_contains(&self.0, point)
+    }
+
     pub fn ray_collide(&self, transform: &Transform, pos: Vec2, dir: Vec2) -> Option<(Vec2, Vec2)> {
         let translation = transform.translation.xy();
         let mut iter = self.0.iter();
@@ -49,6 +59,24 @@ impl Collider {
     }
 }
 
+///Even-odd test, whether `point` is inside the polygon. The last corner connects to the first one.
+pub fn polygon_contains(points: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    let mut c0 = match points.last() {
+        Some(&c0) => c0,
+        None => return false,
+    };
+    for &c1 in points {
+        if (c0.y > point.y) != (c1.y > point.y)
+            && point.x < c0.x + (point.y - c0.y) / (c1.y - c0.y) * (c1.x - c0.x)
+        {
+            inside = !inside;
+        }
+        c0 = c1;
+    }
+    inside
+}
+
 pub struct Block {
     _size: Vec2,
 }
@@ -118,3 +146,15 @@ fn test_collider() {
         println!("{:?}", result);
     }
 }
+
+#[test]
+fn test_contains_point() {
+    let triangle = Triangle::new_collider((2.0, 2.0).into());
+    assert!(triangle.contains_point(Vec2::new(0.5, -0.5)));
+    assert!(!triangle.contains_point(Vec2::new(-0.5, 0.5)));
+    assert!(!triangle.contains_point(Vec2::new(1.5, 0.0)));
+
+    let block = Block::new_collider((2.0, 2.0).into());
+    assert!(block.contains_point(Vec2::new(-0.9, 0.9)));
+    assert!(!block.contains_point(Vec2::new(0.0, 1.1)));
+}
@@ -1,10 +1,17 @@
 use bevy::{ecs::schedule::ShouldRun, prelude::*, render2::render_phase::TrackedRenderPass};
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    action::Action,
+    click::{ClickEvent, Clickable},
     collider2d::{self, Collider},
     game_state::*,
-    window::{get_3d_from_cord, COLUMNS, HEIGHT, ROWS, SIZE, SIZE_MULTIPLIER, WIDTH},
+    grid::{BoardRes, GridPos},
+    settings::GameSettingsRes,
+    theme::{ThemeMaterialsRes, ThemedText},
+    timer::TimeScaleRes,
+    window::BoardConfigRes,
 };
 
 //TODO Import Const
@@ -15,19 +22,18 @@ impl Plugin for ElementPlugin {
         //Spawn
         app.add_startup_system(startup_spawn_world_collider)
             .add_system_set(
-                SystemSet::new()
+                SystemSet::on_enter(GameState::GenerateObstacle)
                     .label(GenerateElementLabel::Main)
-                    .with_run_criteria(IS_GENERATE_OBSTACLE_STATE)
                     .with_system(
                         generate_next_elements_system
                             .system()
-                            .label(GenerateElementLabel::Generate),
+                            .label(GenerateElementLabel::Generate)
+                            .after(GameStateLabel::Track),
                     ),
             )
             .add_system_set(
-                SystemSet::new()
+                SystemSet::on_enter(GameState::SpawnObstacle)
                     .label(SpawnElementLabel::Main)
-                    .with_run_criteria(IS_SPAWN_OBSTACLE_STATE)
                     .with_system(spawn_block_system.system().label(SpawnElementLabel::Spawn))
                     .with_system(
                         spawn_powerup_laser_system
@@ -42,19 +48,29 @@ impl Plugin for ElementPlugin {
                     ),
             )
             .add_system_set(
-                SystemSet::new()
+                SystemSet::on_update(GameState::MoveObstacle)
                     .label(MoveElementLabel::Main)
-                    .with_run_criteria(IS_MOVE_OBSTACLE_STATE)
                     .with_system(move_system.system().label(MoveElementLabel::Move)),
-            );
+            )
+            .add_system(inspect_live_system)
+            .add_system(live_display_system)
+            .add_system(destroy_block_system);
     }
 }
 
 pub struct Element;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ElementKind {
+    Block,
+    PowerupAddLaser,
+    Bounce,
+}
+
+///Every element on the board carries it. The destination is the position of the element after the current move.
 #[derive(Component)]
-struct AnimationMoveDown {
-    destination: Vec3,
+pub struct AnimationMoveDown {
+    pub destination: Vec3,
 }
 // pub struct AnimationLoseLive;
 // pub struct AnimationSpawn;
@@ -67,6 +83,13 @@ pub struct Block;
 //pub struct Live;
 #[derive(Component)]
 pub struct Live(pub i32);
+///Lives of the element when it was spawned, the colour of a block shows the share left
+#[derive(Component)]
+pub struct SpawnLive(pub i32);
+
+///Shows the `Live` of its parent block
+#[derive(Component)]
+struct LiveText;
 
 #[derive(Component)]
 pub struct PowerupAddLaser;
@@ -74,14 +97,15 @@ pub struct PowerupAddLaser;
 #[derive(Component)]
 pub struct Bounce;
 
-fn startup_spawn_world_collider(mut commands: Commands) {
+fn startup_spawn_world_collider(mut commands: Commands, board: Res<BoardConfigRes>) {
+    let (width, height) = (board.width(), board.height());
     commands
         .spawn()
         .insert(Collider::new(vec![
-            Vec2::new(WIDTH / 2.0, -HEIGHT / 2.0),
-            Vec2::new(WIDTH / 2.0, HEIGHT / 2.0),
-            Vec2::new(-WIDTH / 2.0, HEIGHT / 2.0),
-            Vec2::new(-WIDTH / 2.0, -HEIGHT / 2.0),
+            Vec2::new(width / 2.0, -height / 2.0),
+            Vec2::new(width / 2.0, height / 2.0),
+            Vec2::new(-width / 2.0, height / 2.0),
+            Vec2::new(-width / 2.0, -height / 2.0),
         ]))
         .insert(Transform::default());
 }
@@ -93,25 +117,44 @@ pub enum GenerateElementLabel {
     Generate,
 }
 
-pub fn generate_next_elements_system(mut commands: Commands, mut game_state: ResMut<GameStateRes>) {
+pub fn generate_next_elements_system(
+    mut commands: Commands,
+    mut game_state: ResMut<State<GameState>>,
+    mut turn: ResMut<TurnRes>,
+    mut random: ResMut<RngRes>,
+    board: Res<BoardConfigRes>,
+    board_res: Res<BoardRes>,
+    settings: Res<GameSettingsRes>,
+) {
     //TODO Move to spawn options
-    let block_probability = 0.3;
+    let block_probability = settings.difficulty.block_probability();
     let triangle_probability = 0.1;
     let bounce_probability = 0.1;
 
-    let powerup_laser_position = rand::thread_rng().gen_range(1u8..(COLUMNS as u8));
+    turn.0 += 1;
+
+    let powerup_laser_position = random.rng().gen_range(1u8..(board.columns as u8));
     info!("Powerup Laser pos {}", powerup_laser_position);
-    for element in 0..(COLUMNS as u8) {
+    for element in 0..(board.columns as u8) {
+        let grid_pos = GridPos::new(element as i32, 0);
+        if board_res.is_occupied(grid_pos) {
+            warn!("Cell {:?} is still occupied", grid_pos);
+            continue;
+        }
         let mut o_entity = None;
         if element == powerup_laser_position {
             let mut entity = commands.spawn();
             entity.insert(PowerupAddLaser);
             o_entity = Some(entity);
         } else {
-            let rng = rand::thread_rng().gen();
+            let rng = random.rng().gen();
             if block_probability <= rng {
                 let mut entity = commands.spawn();
-                entity.insert(Block);
+                let live = turn.0 as i32;
+                entity
+                    .insert(Block)
+                    .insert(Live(live))
+                    .insert(SpawnLive(live));
                 o_entity = Some(entity);
             // } else if block_probability + triangle_probability <= rng {
             //     commands.spawn().insert(Triangle);
@@ -122,12 +165,14 @@ pub fn generate_next_elements_system(mut commands: Commands, mut game_state: Res
             }
         }
         if let Some(mut entity) = o_entity {
-            let pos = Vec3::from(get_3d_from_cord(element as f32, 0.0, 0.0));
-            entity.insert(AnimationMoveDown { destination: pos });
+            let pos = grid_pos.to_world(&board, 0.0);
+            entity
+                .insert(grid_pos)
+                .insert(AnimationMoveDown { destination: pos });
         }
     }
 
-    game_state.change(GameState::SpawnObstacle);
+    change_state(&mut game_state, GameState::SpawnObstacle);
 }
 //endregion
 
@@ -142,52 +187,215 @@ pub enum SpawnElementLabel {
 fn spawn_block_system(
     mut commands: Commands,
     query: Query<(Entity, &AnimationMoveDown), (With<Block>, Without<Transform>)>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    theme_materials: Res<ThemeMaterialsRes>,
+    board: Res<BoardConfigRes>,
 ) {
     for (entity, animation_move_down) in query.iter() {
-        commands
-            .entity(entity)
-            .insert_bundle(SpriteBundle {
-                sprite: Sprite::new((SIZE * 0.95, SIZE * 0.95).into()),
-                material: materials.add(Color::rgb(0.0, 0.0, 1.0).into()),
-                transform: Transform::from_translation(animation_move_down.destination),
+        insert_block(
+            &mut commands,
+            entity,
+            &theme_materials,
+            animation_move_down.destination,
+            board.size(),
+        );
+    }
+}
+fn insert_block(
+    commands: &mut Commands,
+    entity: Entity,
+    theme_materials: &ThemeMaterialsRes,
+    pos: Vec3,
+    size: f32,
+) {
+    let collider = collider2d::Block::new_collider(Vec2::new(size, size));
+    commands
+        .entity(entity)
+        .insert_bundle(SpriteBundle {
+            sprite: Sprite::new((size * 0.95, size * 0.95).into()),
+            material: theme_materials.block(1.0).clone(),
+            transform: Transform::from_translation(pos),
+            ..Default::default()
+        })
+        .insert(Clickable {
+            buttons: vec![Action::Context],
+            ..Clickable::new((&collider).into())
+        })
+        .insert(collider)
+        .with_children(|parent| {
+            parent.spawn_bundle(SpriteBundle {
+                sprite: Sprite::new((size * 0.85, size * 0.85).into()),
+                material: theme_materials.block_inner.clone(),
+                transform: Transform::from_translation((0.0, 0.0, 1.0).into()),
                 ..Default::default()
-            })
-            .insert(collider2d::Block::new_collider(Vec2::new(SIZE, SIZE)))
-            .with_children(|parent| {
-                parent.spawn_bundle(SpriteBundle {
-                    sprite: Sprite::new((SIZE * 0.85, SIZE * 0.85).into()),
-                    material: materials.add(Color::rgb(0.0, 0.0, 0.0).into()),
-                    transform: Transform::from_translation((0.0, 0.0, 1.0).into()),
-                    ..Default::default()
-                });
             });
-    }
+            //Filled by `live_display_system`
+            parent
+                .spawn_bundle(Text2dBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: theme_materials.font.clone(),
+                            font_size: size * 0.5,
+                            color: theme_materials.text,
+                        },
+                        TextAlignment {
+                            vertical: VerticalAlign::Center,
+                            horizontal: HorizontalAlign::Center,
+                        },
+                    ),
+                    transform: Transform::from_translation((0.0, 0.0, 2.0).into()),
+                    ..Default::default()
+                })
+                .insert(LiveText)
+                .insert(ThemedText);
+        });
 }
 fn spawn_powerup_laser_system(
     mut commands: Commands,
     query: Query<(Entity, &AnimationMoveDown), (With<PowerupAddLaser>, Without<Transform>)>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    theme_materials: Res<ThemeMaterialsRes>,
+    board: Res<BoardConfigRes>,
 ) {
     for (entity, animation_move_down) in query.iter() {
-        commands.entity(entity).insert_bundle(SpriteBundle {
-            sprite: Sprite::new((SIZE / 2.0, SIZE / 2.0).into()),
-            material: materials.add(Color::rgb(0.0, 1.0, 0.0).into()),
-            transform: Transform::from_translation(animation_move_down.destination),
-            ..Default::default()
-        });
+        insert_powerup_laser(
+            &mut commands,
+            entity,
+            &theme_materials,
+            animation_move_down.destination,
+            board.size(),
+        );
+    }
+}
+fn insert_powerup_laser(
+    commands: &mut Commands,
+    entity: Entity,
+    theme_materials: &ThemeMaterialsRes,
+    pos: Vec3,
+    size: f32,
+) {
+    commands.entity(entity).insert_bundle(SpriteBundle {
+        sprite: Sprite::new((size / 2.0, size / 2.0).into()),
+        material: theme_materials.powerup_laser.clone(),
+        transform: Transform::from_translation(pos),
+        ..Default::default()
+    });
+}
+
+///Context click on an element logs its lives
+fn inspect_live_system(mut click_events: EventReader<ClickEvent>, query: Query<&Live>) {
+    for event in click_events
+        .iter()
+        .filter(|event| event.button == Action::Context)
+    {
+        if let Ok(live) = query.get(event.entity) {
+            info!("Element {:?} has {} lives", event.entity, live.0);
+        }
+    }
+}
+
+///Updates the counter and the colour of blocks, whenever their lives change
+fn live_display_system(
+    theme_materials: Res<ThemeMaterialsRes>,
+    mut query_blocks: Query<
+        (&Live, &SpawnLive, &Children, &mut Handle<ColorMaterial>),
+        (With<Block>, Or<(Changed<Live>, Changed<Children>)>),
+    >,
+    mut query_text: Query<&mut Text, With<LiveText>>,
+) {
+    for (live, spawn_live, children, mut material) in query_blocks.iter_mut() {
+        *material = theme_materials
+            .block(live.0 as f32 / spawn_live.0.max(1) as f32)
+            .clone();
+        for child in children.iter() {
+            if let Ok(mut text) = query_text.get_mut(*child) {
+                text.sections[0].value = live_label(live);
+            }
+        }
+    }
+}
+
+///Counter shown on a block, never negative
+fn live_label(live: &Live) -> String {
+    live.0.max(0).to_string()
+}
+
+///Blocks without lives left are removed from the board
+fn destroy_block_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Live), (With<Block>, Changed<Live>)>,
+) {
+    for (entity, live) in query.iter() {
+        if live.0 <= 0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+///Spawns an element directly at its position, without the generate and spawn states. Used to restore a stored board.
+///`live` holds the current and the spawn lives.
+pub fn spawn_element(
+    commands: &mut Commands,
+    theme_materials: &ThemeMaterialsRes,
+    kind: ElementKind,
+    pos: Vec3,
+    live: Option<(i32, i32)>,
+    board: &BoardConfigRes,
+) -> Entity {
+    let size = board.size();
+    let entity = commands
+        .spawn()
+        .insert(GridPos::from_world(board, pos.truncate()))
+        .insert(AnimationMoveDown { destination: pos })
+        .id();
+    match kind {
+        ElementKind::Block => {
+            commands.entity(entity).insert(Block);
+            insert_block(commands, entity, theme_materials, pos, size);
+        }
+        ElementKind::PowerupAddLaser => {
+            commands.entity(entity).insert(PowerupAddLaser);
+            insert_powerup_laser(commands, entity, theme_materials, pos, size);
+        }
+        ElementKind::Bounce => {
+            commands.entity(entity).insert(Bounce);
+        }
+    }
+    if let Some((live, spawn_live)) = live {
+        commands
+            .entity(entity)
+            .insert(Live(live))
+            .insert(SpawnLive(spawn_live));
+    }
+    entity
+}
+
+pub fn element_kind(
+    block: Option<&Block>,
+    powerup_laser: Option<&PowerupAddLaser>,
+    bounce: Option<&Bounce>,
+) -> Option<ElementKind> {
+    if block.is_some() {
+        Some(ElementKind::Block)
+    } else if powerup_laser.is_some() {
+        Some(ElementKind::PowerupAddLaser)
+    } else if bounce.is_some() {
+        Some(ElementKind::Bounce)
+    } else {
+        None
     }
 }
 
 fn init_move_system(
-    mut game_state: ResMut<GameStateRes>,
-    mut query: Query<&mut AnimationMoveDown>,
+    mut game_state: ResMut<State<GameState>>,
+    mut query: Query<(&mut AnimationMoveDown, &mut GridPos)>,
+    board: Res<BoardConfigRes>,
 ) {
-    for mut animation_move_down in query.iter_mut() {
+    for (mut animation_move_down, mut grid_pos) in query.iter_mut() {
+        *grid_pos = grid_pos.below();
         animation_move_down.destination =
-            animation_move_down.destination + Vec3::new(0.0, -SIZE, 0.0);
+            grid_pos.to_world(&board, animation_move_down.destination.z);
     }
-    game_state.change(GameState::MoveObstacle);
+    change_state(&mut game_state, GameState::MoveObstacle);
 }
 //endregion
 
@@ -199,15 +407,25 @@ pub enum MoveElementLabel {
 }
 
 fn move_system(
-    mut game_state: ResMut<GameStateRes>,
-    mut query: Query<(&mut Transform, &AnimationMoveDown)>,
+    mut commands: Commands,
+    mut game_state: ResMut<State<GameState>>,
+    mode: Res<GameModeRes>,
+    mut query: Query<(
+        Entity,
+        &mut Transform,
+        &AnimationMoveDown,
+        &GridPos,
+        Option<&Block>,
+    )>,
     time: Res<Time>,
+    time_scale: Res<TimeScaleRes>,
+    board: Res<BoardConfigRes>,
 ) {
     let mut in_movement = false;
-    let max_length = SIZE_MULTIPLIER / 2.0 * time.delta_seconds() * 15.0;
+    let max_length = board.size_multiplier / 2.0 * time_scale.delta_seconds(&time) * 15.0;
     let max_length_squared = max_length * max_length;
 
-    for (mut transform, animation_move_down) in query.iter_mut() {
+    for (_, mut transform, animation_move_down, ..) in query.iter_mut() {
         let vec = animation_move_down.destination - transform.translation;
         if vec.length_squared() <= max_length_squared {
             transform.translation = animation_move_down.destination;
@@ -218,7 +436,47 @@ fn move_system(
     }
 
     if !in_movement {
-        game_state.change(GameState::AimingLaser);
+        let mut game_over = false;
+        for (entity, _, _, grid_pos, o_block) in query.iter() {
+            if reached_player(&board, *grid_pos) {
+                if o_block.is_some() && mode.0 == GameMode::Classic {
+                    game_over = true;
+                } else {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+
+        if game_over {
+            change_state(&mut game_state, GameState::GameOver);
+        } else {
+            change_state(&mut game_state, GameState::AimingLaser);
+        }
     }
 }
+
+///Elements in the row of the player
+fn reached_player(board: &BoardConfigRes, grid_pos: GridPos) -> bool {
+    grid_pos.row >= board.rows as i32 - 2
+}
 //endregion
+
+#[test]
+fn test_destroy_block() {
+    use bevy::ecs::schedule::Stage;
+
+    let mut world = World::default();
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(destroy_block_system);
+
+    let block = world.spawn().insert(Block).insert(Live(1)).id();
+    stage.run(&mut world);
+    assert!(world.get_entity(block).is_some());
+
+    world.get_mut::<Live>(block).unwrap().0 = 0;
+    stage.run(&mut world);
+    assert!(world.get_entity(block).is_none());
+
+    assert_eq!(live_label(&Live(3)), "3");
+    assert_eq!(live_label(&Live(-1)), "0");
+}
@@ -1,64 +1,389 @@
-use bevy::{ecs::schedule::ShouldRun, prelude::*};
-
-// region [rgba(256,256,0,0.2)] State Functions
-
-pub const IS_AIMING_LASER_STATE: for<'r> fn(bevy::prelude::Res<'r, GameStateRes>) -> ShouldRun =
-    is_game_state::<{ GameState::AimingLaser }>;
-pub const IS_MOVE_PLAYER_STATE: for<'r> fn(bevy::prelude::Res<'r, GameStateRes>) -> ShouldRun =
-    is_game_state::<{ GameState::MovePlayer }>;
-pub const IS_FIRE_LASER_STATE: for<'r> fn(bevy::prelude::Res<'r, GameStateRes>) -> ShouldRun =
-    is_game_state::<{ GameState::FireLaser }>;
-pub const IS_GENERATE_OBSTACLE_STATE: for<'r> fn(
-    bevy::prelude::Res<'r, GameStateRes>,
-) -> ShouldRun = is_game_state::<{ GameState::GenerateObstacle }>;
-pub const IS_SPAWN_OBSTACLE_STATE: for<'r> fn(bevy::prelude::Res<'r, GameStateRes>) -> ShouldRun =
-    is_game_state::<{ GameState::SpawnObstacle }>;
-pub const IS_MOVE_OBSTACLE_STATE: for<'r> fn(bevy::prelude::Res<'r, GameStateRes>) -> ShouldRun =
-    is_game_state::<{ GameState::MoveObstacle }>;
-
-fn is_game_state<const T: GameState>(game_state: Res<GameStateRes>) -> ShouldRun {
-    if game_state.state.eq(&T) {
-        ShouldRun::Yes
-    } else {
-        ShouldRun::No
-    }
-}
-//endregion
+use std::collections::VecDeque;
+
+use bevy::{ecs::schedule::StateError, prelude::*};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
 pub struct GameStatePlugin;
 
 impl Plugin for GameStatePlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(GameStateRes::default());
+        app.add_state(GameState::None)
+            .add_event::<GameStateChanged>()
+            .insert_resource(GameStateHistoryRes::default())
+            .insert_resource(GameModeRes::default())
+            .insert_resource(TurnRes::default())
+            .insert_resource(RngRes::default());
+
+        for state in GameState::ALL {
+            app.add_system_set(
+                SystemSet::on_enter(state)
+                    .with_system(track_game_state_system.label(GameStateLabel::Track)),
+            )
+            .add_system_set(
+                SystemSet::on_resume(state)
+                    .with_system(track_game_state_system.label(GameStateLabel::Track)),
+            );
+        }
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub enum GameStateLabel {
+    ///Sends `GameStateChanged`, before the on enter systems of the new state run
+    Track,
+}
+
+///Driven by `State<GameState>`. One-shot systems run in `SystemSet::on_enter`, per frame systems in `SystemSet::on_update`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub enum GameState {
+    ///Nothing is running, until the game is started
     None,
+    MainMenu,
+    Settings,
     AimingLaser,
     MovePlayer,
     FireLaser,
     GenerateObstacle,
     SpawnObstacle,
     MoveObstacle,
+    GameOver,
+    Paused,
+}
+
+impl GameState {
+    pub const ALL: [GameState; 11] = [
+        GameState::None,
+        GameState::MainMenu,
+        GameState::Settings,
+        GameState::AimingLaser,
+        GameState::MovePlayer,
+        GameState::FireLaser,
+        GameState::GenerateObstacle,
+        GameState::SpawnObstacle,
+        GameState::MoveObstacle,
+        GameState::GameOver,
+        GameState::Paused,
+    ];
+
+    ///Allowed transitions of `change_state` and `pause`. Every running state can be paused,
+    ///a pause is only left by a restart or by `resume` to the paused state.
+    pub fn can_change_to(self, to: GameState) -> bool {
+        use GameState::*;
+        matches!(
+            (self, to),
+            (None, MainMenu)
+                | (None, GenerateObstacle)
+                | (MainMenu, GenerateObstacle)
+                | (MainMenu, AimingLaser)
+                | (MainMenu, Settings)
+                | (Settings, MainMenu)
+                | (GenerateObstacle, SpawnObstacle)
+                | (SpawnObstacle, MoveObstacle)
+                | (MoveObstacle, AimingLaser)
+                | (MoveObstacle, GameOver)
+                | (AimingLaser, MovePlayer)
+                | (AimingLaser, FireLaser)
+                | (MovePlayer, AimingLaser)
+                | (FireLaser, GenerateObstacle)
+                | (GameOver, MainMenu)
+                | (Paused, GenerateObstacle)
+        ) || (to == Paused && self.is_in_game())
+    }
+
+    ///States of a running game, that can be paused
+    pub fn is_in_game(self) -> bool {
+        use GameState::*;
+        matches!(
+            self,
+            AimingLaser | MovePlayer | FireLaser | GenerateObstacle | SpawnObstacle | MoveObstacle
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameStateChanged {
+    pub from: GameState,
+    pub to: GameState,
+    pub turn: u32,
+}
+
+#[derive(Debug)]
+pub enum GameStateError {
+    IllegalTransition { from: GameState, to: GameState },
+    Schedule(StateError),
+}
+
+pub const GAME_STATE_HISTORY_LENGTH: usize = 32;
+
+///The last `GAME_STATE_HISTORY_LENGTH` state changes, the newest at the end
+#[derive(Default)]
+pub struct GameStateHistoryRes {
+    history: VecDeque<GameStateChanged>,
+}
+
+impl GameStateHistoryRes {
+    pub fn push(&mut self, change: GameStateChanged) {
+        if self.history.len() == GAME_STATE_HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(change);
+    }
+
+    pub fn last(&self) -> Option<&GameStateChanged> {
+        self.history.back()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &GameStateChanged> {
+        self.history.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
 }
 
-pub struct GameStateRes {
-    state: GameState,
+fn track_game_state_system(
+    game_state: Res<State<GameState>>,
+    turn: Res<TurnRes>,
+    mut history: ResMut<GameStateHistoryRes>,
+    mut game_state_changed_events: EventWriter<GameStateChanged>,
+) {
+    let to = *game_state.current();
+    let from = history.last().map_or(GameState::None, |change| change.to);
+    if from != to {
+        info!("Changed Game State ({:?} -> {:?})", from, to);
+        let change = GameStateChanged {
+            from,
+            to,
+            turn: turn.0,
+        };
+        history.push(change);
+        game_state_changed_events.send(change);
+    }
 }
 
-impl GameStateRes {
-    pub fn change(&mut self, game_state: GameState) {
-        info!("Changed Game State ({:?})", game_state);
-        self.state = game_state;
+fn check_transition(state: &State<GameState>, to: GameState) -> Result<(), GameStateError> {
+    let from = *state.current();
+    if from.can_change_to(to) {
+        Ok(())
+    } else {
+        Err(GameStateError::IllegalTransition { from, to })
     }
 }
 
-impl Default for GameStateRes {
+///Replaces the current state. A change, that is already scheduled in this frame, is overwritten.
+///Changing from `GameState::Paused` also drops the paused state, e.g. to restart.
+///Transitions, that are not allowed by `GameState::can_change_to`, are rejected.
+pub fn try_change_state(
+    state: &mut State<GameState>,
+    game_state: GameState,
+) -> Result<(), GameStateError> {
+    check_transition(state, game_state)?;
+    let result = if is_paused(state) {
+        state.overwrite_replace(game_state)
+    } else {
+        state.overwrite_set(game_state)
+    };
+    result.map_err(GameStateError::Schedule)
+}
+
+///Like `try_change_state`, but logs the error
+pub fn change_state(state: &mut State<GameState>, game_state: GameState) {
+    if let Err(err) = try_change_state(state, game_state) {
+        error!("Could not change Game State: {:?}", err);
+    }
+}
+
+///Stops all game state systems until `try_resume` is called
+pub fn try_pause(state: &mut State<GameState>) -> Result<(), GameStateError> {
+    check_transition(state, GameState::Paused)?;
+    state
+        .push(GameState::Paused)
+        .map_err(GameStateError::Schedule)
+}
+
+///Continues the running state below the pause
+pub fn try_resume(state: &mut State<GameState>) -> Result<(), GameStateError> {
+    let from = *state.current();
+    let to = state.inactives().last().copied().unwrap_or(GameState::None);
+    if from != GameState::Paused || !to.is_in_game() {
+        return Err(GameStateError::IllegalTransition { from, to });
+    }
+    state.pop().map_err(GameStateError::Schedule)
+}
+
+///Like `try_pause`, but logs the error
+pub fn pause(state: &mut State<GameState>) {
+    let current = *state.current();
+    match try_pause(state) {
+        Ok(()) => info!("Paused Game State ({:?})", current),
+        Err(err) => warn!("Could not pause Game State: {:?}", err),
+    }
+}
+
+///Like `try_resume`, but logs the error
+pub fn resume(state: &mut State<GameState>) {
+    let paused = state.inactives().last().copied();
+    match try_resume(state) {
+        Ok(()) => info!("Resumed Game State ({:?})", paused),
+        Err(err) => warn!("Could not resume Game State: {:?}", err),
+    }
+}
+
+pub fn is_paused(state: &State<GameState>) -> bool {
+    *state.current() == GameState::Paused
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    ///The game is over, when a block reaches the player
+    Classic,
+    ///Elements that reach the player are removed
+    Endless,
+    ///Like `Endless`, but every turn can be reverted
+    Practice,
+}
+
+///Selected in the main menu
+pub struct GameModeRes(pub GameMode);
+
+impl Default for GameModeRes {
     fn default() -> Self {
+        Self(GameMode::Classic)
+    }
+}
+
+///Number of obstacle rows generated in the current run
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+pub struct TurnRes(pub u32);
+
+///Seeded random generator, so a run can be stored and continued with the same obstacles
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RngRes {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl RngRes {
+    pub fn from_seed(seed: u64) -> Self {
         Self {
-            state: GameState::GenerateObstacle,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
         }
     }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn rng(&mut self) -> &mut ChaCha8Rng {
+        &mut self.rng
+    }
+}
+
+impl Default for RngRes {
+    fn default() -> Self {
+        Self::from_seed(rand::random())
+    }
+}
+
+#[test]
+fn test_game_state_transitions() {
+    assert!(GameState::AimingLaser.can_change_to(GameState::FireLaser));
+    assert!(GameState::FireLaser.can_change_to(GameState::GenerateObstacle));
+    assert!(!GameState::FireLaser.can_change_to(GameState::MovePlayer));
+    assert!(!GameState::GenerateObstacle.can_change_to(GameState::AimingLaser));
+
+    let mut state = State::new(GameState::FireLaser);
+    assert!(matches!(
+        try_change_state(&mut state, GameState::MovePlayer),
+        Err(GameStateError::IllegalTransition {
+            from: GameState::FireLaser,
+            to: GameState::MovePlayer
+        })
+    ));
+    assert!(try_change_state(&mut state, GameState::GenerateObstacle).is_ok());
+
+    assert!(GameState::MoveObstacle.can_change_to(GameState::Paused));
+    assert!(!GameState::MainMenu.can_change_to(GameState::Paused));
+    assert!(GameState::Paused.can_change_to(GameState::GenerateObstacle));
+    assert!(!GameState::Paused.can_change_to(GameState::Settings));
+    let mut state = State::new(GameState::MainMenu);
+    assert!(try_pause(&mut state).is_err());
+    assert!(try_resume(&mut state).is_err());
+}
+
+#[test]
+fn test_pause_transitions() {
+    use bevy::ecs::schedule::Stage;
+
+    let mut world = World::default();
+    world.insert_resource(State::new(GameState::FireLaser));
+    let mut stage =
+        SystemStage::single_threaded().with_system_set(State::<GameState>::get_driver());
+
+    try_pause(&mut world.get_resource_mut::<State<GameState>>().unwrap()).unwrap();
+    stage.run(&mut world);
+    let mut state = world.get_resource_mut::<State<GameState>>().unwrap();
+    assert_eq!(state.current(), &GameState::Paused);
+    assert_eq!(state.inactives(), &[GameState::FireLaser]);
+
+    //Only the paused state can be continued
+    assert!(matches!(
+        try_change_state(&mut state, GameState::MovePlayer),
+        Err(GameStateError::IllegalTransition {
+            from: GameState::Paused,
+            to: GameState::MovePlayer
+        })
+    ));
+    assert!(try_change_state(&mut state, GameState::FireLaser).is_err());
+    assert!(try_resume(&mut state).is_ok());
+    stage.run(&mut world);
+    let state = world.get_resource::<State<GameState>>().unwrap();
+    assert_eq!(state.current(), &GameState::FireLaser);
+    assert!(state.inactives().is_empty());
+}
+
+#[test]
+fn test_game_state_history() {
+    use bevy::{app::Events, ecs::schedule::Stage};
+
+    let mut world = World::default();
+    world.insert_resource(State::new(GameState::AimingLaser));
+    world.insert_resource(TurnRes(3));
+    world.insert_resource(GameStateHistoryRes::default());
+    world.insert_resource(Events::<GameStateChanged>::default());
+
+    let mut stage = SystemStage::parallel();
+    stage.add_system(track_game_state_system);
+    stage.run(&mut world);
+
+    let history = world.get_resource::<GameStateHistoryRes>().unwrap();
+    assert_eq!(
+        history.last(),
+        Some(&GameStateChanged {
+            from: GameState::None,
+            to: GameState::AimingLaser,
+            turn: 3
+        })
+    );
+    let events = world.get_resource::<Events<GameStateChanged>>().unwrap();
+    assert_eq!(events.get_reader().iter(events).count(), 1);
+
+    let mut history = GameStateHistoryRes::default();
+    for turn in 0..(GAME_STATE_HISTORY_LENGTH as u32 + 10) {
+        history.push(GameStateChanged {
+            from: GameState::AimingLaser,
+            to: GameState::FireLaser,
+            turn,
+        });
+    }
+    assert_eq!(history.len(), GAME_STATE_HISTORY_LENGTH);
+    assert_eq!(history.iter().next().unwrap().turn, 10);
 }
@@ -0,0 +1,73 @@
+use bevy::{
+    input::{
+        gamepad::{Gamepad, GamepadEvent, GamepadEventType},
+        InputSystem,
+    },
+    prelude::*,
+};
+
+///Stick values with a smaller length are ignored
+pub const STICK_DEADZONE: f32 = 0.25;
+
+///Tracks the gamepad used for the game. Its buttons and left stick are mapped in `ActionPlugin`.
+pub struct GamepadPlugin;
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ActiveGamepadRes::default())
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                gamepad_connection_system
+                    .label(GamepadLabel::Connection)
+                    .after(InputSystem),
+            );
+    }
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub enum GamepadLabel {
+    Connection,
+}
+
+///Gamepad used for the game, the first one connected
+#[derive(Default)]
+pub struct ActiveGamepadRes(pub Option<Gamepad>);
+
+///Direction the stick points to, `None` inside the deadzone
+pub fn stick_direction(stick: Vec2) -> Option<Vec2> {
+    if stick.length() < STICK_DEADZONE {
+        None
+    } else {
+        Some(stick)
+    }
+}
+
+pub fn gamepad_connection_system(
+    mut active_gamepad: ResMut<ActiveGamepadRes>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+) {
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected if active_gamepad.0.is_none() => {
+                info!("Use gamepad {:?}", gamepad);
+                active_gamepad.0 = Some(*gamepad);
+            }
+            GamepadEventType::Disconnected if active_gamepad.0 == Some(*gamepad) => {
+                info!("Gamepad {:?} disconnected", gamepad);
+                active_gamepad.0 = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_stick_to_angle() {
+    use crate::player::{aim_angle, MAX_ANGLE};
+
+    let stick_to_angle = |stick| stick_direction(stick).and_then(|stick| aim_angle(stick, false));
+    assert_eq!(stick_to_angle(Vec2::new(0.1, 0.1)), None);
+    assert_eq!(stick_to_angle(Vec2::Y), Some(0.0));
+    assert_eq!(stick_to_angle(Vec2::X), Some(-MAX_ANGLE));
+    assert_eq!(stick_to_angle(-Vec2::Y), None);
+    assert_eq!(stick_to_angle(Vec2::new(-1.0, -1.0)), Some(MAX_ANGLE));
+}
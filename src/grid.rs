@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::window::BoardConfigRes;
+
+///Keeps `BoardRes` in sync with the `GridPos` of the elements
+pub struct GridPlugin;
+impl Plugin for GridPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BoardRes::default())
+            //After the despawns of the update stage, so their removal is seen
+            .add_system_to_stage(CoreStage::PostUpdate, sync_board_system);
+    }
+}
+
+///Cell of an element on the board. Row 0 is the top row, elements move down one row per turn.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GridPos {
+    pub column: i32,
+    pub row: i32,
+}
+
+impl GridPos {
+    pub fn new(column: i32, row: i32) -> Self {
+        Self { column, row }
+    }
+
+    ///Cell containing the world position, inverse of `to_world`
+    pub fn from_world(board: &BoardConfigRes, position: Vec2) -> Self {
+        let (x, y, _) = board.get_cord_from_3d(position.x, position.y, 0.0);
+        Self::new(x.round() as i32, y.round() as i32)
+    }
+
+    ///Center of the cell in world coordinates
+    pub fn to_world(self, board: &BoardConfigRes, z: f32) -> Vec3 {
+        board
+            .get_3d_from_cord(self.column as f32, self.row as f32, z)
+            .into()
+    }
+
+    ///The cell one row further down
+    pub fn below(self) -> Self {
+        Self::new(self.column, self.row + 1)
+    }
+}
+
+///Elements by the cell they occupy
+#[derive(Default)]
+pub struct BoardRes(HashMap<GridPos, Entity>);
+
+impl BoardRes {
+    pub fn get(&self, grid_pos: GridPos) -> Option<Entity> {
+        self.0.get(&grid_pos).copied()
+    }
+
+    pub fn is_occupied(&self, grid_pos: GridPos) -> bool {
+        self.get(grid_pos).is_some()
+    }
+
+    ///Occupies the cell, the entity already in it is returned
+    pub fn insert(&mut self, grid_pos: GridPos, entity: Entity) -> Option<Entity> {
+        self.0.insert(grid_pos, entity)
+    }
+}
+
+///Rebuilds the board, whenever an element is moved, added or removed
+fn sync_board_system(
+    mut board: ResMut<BoardRes>,
+    query: Query<(Entity, &GridPos)>,
+    query_changed: Query<(), Changed<GridPos>>,
+    removed: RemovedComponents<GridPos>,
+) {
+    if query_changed.is_empty() && removed.iter().next().is_none() {
+        return;
+    }
+    board.0.clear();
+    for (entity, grid_pos) in query.iter() {
+        if let Some(other) = board.insert(*grid_pos, entity) {
+            warn!("{:?} and {:?} overlap at {:?}", entity, other, grid_pos);
+        }
+    }
+}
+
+#[test]
+fn test_grid_pos() {
+    let board = BoardConfigRes::default();
+    let grid_pos = GridPos::new(2, 5);
+    let position = grid_pos.to_world(&board, 1.0);
+    assert_eq!(position.z, 1.0);
+    assert_eq!(GridPos::from_world(&board, position.truncate()), grid_pos);
+    //Anywhere in the cell
+    assert_eq!(
+        GridPos::from_world(&board, position.truncate() + Vec2::new(15.0, -15.0)),
+        grid_pos
+    );
+    assert_eq!(
+        grid_pos.below().to_world(&board, 1.0),
+        position - Vec3::new(0.0, board.size(), 0.0)
+    );
+}
+
+#[test]
+fn test_sync_board() {
+    use bevy::ecs::schedule::Stage;
+
+    let mut world = World::default();
+    world.insert_resource(BoardRes::default());
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(sync_board_system);
+
+    let a = world.spawn().insert(GridPos::new(0, 0)).id();
+    let b = world.spawn().insert(GridPos::new(1, 0)).id();
+    stage.run(&mut world);
+    let board = world.get_resource::<BoardRes>().unwrap();
+    assert_eq!(board.get(GridPos::new(0, 0)), Some(a));
+    assert_eq!(board.get(GridPos::new(1, 0)), Some(b));
+
+    world.get_mut::<GridPos>(a).unwrap().row = 1;
+    world.despawn(b);
+    stage.run(&mut world);
+    let board = world.get_resource::<BoardRes>().unwrap();
+    assert_eq!(board.get(GridPos::new(0, 1)), Some(a));
+    assert!(!board.is_occupied(GridPos::new(0, 0)));
+    assert!(!board.is_occupied(GridPos::new(1, 0)));
+}
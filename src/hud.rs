@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+
+use crate::{
+    game_state::*,
+    player::{cannon_angle, Cannon},
+    theme::{ThemeMaterialsRes, ThemedText},
+};
+
+///Information about the running game in the top left corner
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_hud_system)
+            .add_system(angle_hud_system);
+    }
+}
+
+#[derive(Component)]
+struct AngleText;
+
+///Cannon angle in degrees, positive to the right like on a compass
+pub fn angle_degrees(angle: f32) -> f32 {
+    -angle.to_degrees()
+}
+
+fn spawn_hud_system(mut commands: Commands, theme_materials: Res<ThemeMaterialsRes>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(5.0),
+                    top: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: theme_materials.font.clone(),
+                    font_size: 20.0,
+                    color: theme_materials.text,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(AngleText)
+        .insert(ThemedText);
+}
+
+fn angle_hud_system(
+    game_state: Res<State<GameState>>,
+    query_cannon: Query<&Transform, With<Cannon>>,
+    mut query_text: Query<(&mut Text, &mut Visible), With<AngleText>>,
+) {
+    let (mut text, mut visible) = query_text.single_mut();
+    visible.is_visible = game_state.current().is_in_game();
+    if visible.is_visible {
+        let degrees = angle_degrees(cannon_angle(query_cannon.single()));
+        text.sections[0].value = format!("{:+.0}°", degrees);
+    }
+}
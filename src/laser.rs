@@ -1,20 +1,16 @@
 use crate::{
-    collider2d::Collider,
-    element::Live,
-    game_state::*,
-    player::Cannon,
-    window::{HEIGHT, WIDTH},
+    collider2d::Collider, element::Live, game_state::*, player::Cannon, settings::GameSettingsRes,
+    theme::ThemeMaterialsRes, timer::laser_timestep, window::BoardConfigRes,
 };
 use bevy::{
-    core::FixedTimestep,
     ecs::schedule::ShouldRun,
     math::{Mat2, Vec3Swizzles},
     prelude::*,
 };
+use serde::{Deserialize, Serialize};
 
 pub const AIMING_LASERS: usize = 300;
 
-pub const MAX_LASER_LENGTH: f32 = WIDTH * WIDTH + HEIGHT * HEIGHT; //FIXME take the square root but that is not supported for const.
 pub const HIT_OFFSET: f32 = 0.1;
 pub const LASER_WIDTH: f32 = 4.0;
 
@@ -22,63 +18,59 @@ pub struct LaserPlugin;
 
 impl Plugin for LaserPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(FireLaserRes {
-            amount: 100,
-            shot: 0,
-        })
-        .add_startup_system(aiming_startup)
-        .add_system_set(
-            SystemSet::new()
-                .with_run_criteria(IS_AIMING_LASER_STATE)
-                .label(LaserLabel::Main)
-                .with_system(aiming_system.system().label(LaserLabel::CalculateLaser))
-                .with_system(
-                    drawing_system
-                        .system()
-                        .label(LaserLabel::DrawLaser)
-                        .after(LaserLabel::CalculateLaser),
-                ),
-        )
-        .add_system_set(
-            SystemSet::new()
-                .with_run_criteria(FixedTimestep::step(1f64 / 24f64).chain(run_if_fire_laser_chain))
-                .label(FireLaserLabel::Main)
-                .with_system(hide_aiming_laser_system)
-                .with_system(
-                    instantiate_fire_laser_system
-                        .system()
-                        .label(FireLaserLabel::Instantiate),
-                )
-                .with_system(
-                    shoot_fire_laser_system
-                        .system()
-                        .label(FireLaserLabel::Shoot)
-                        .after(FireLaserLabel::Instantiate),
-                )
-                .with_system(
-                    drawing_system
-                        .system()
-                        .label(FireLaserLabel::Drawing)
-                        .after(FireLaserLabel::Shoot),
-                )
-                .with_system(
-                    hide_fire_laser_system
-                        .system()
-                        .label(FireLaserLabel::Hiding)
-                        .after(FireLaserLabel::Drawing),
-                )
-                .with_system(
-                    detect_end_fire_laser_system
-                        .system()
-                        .after(FireLaserLabel::Hiding)
-                        .label(FireLaserLabel::DetectEnd),
-                ),
-        )
-        .add_system_set(
-            SystemSet::new()
-                .with_run_criteria(IS_MOVE_PLAYER_STATE)
-                .with_system(hide_aiming_laser_system),
-        );
+        app.insert_resource(FireLaserRes::default())
+            .add_startup_system(aiming_startup)
+            .add_system_set(
+                SystemSet::on_update(GameState::AimingLaser)
+                    .label(LaserLabel::Main)
+                    .with_system(aiming_system.system().label(LaserLabel::CalculateLaser))
+                    .with_system(
+                        drawing_system
+                            .system()
+                            .label(LaserLabel::DrawLaser)
+                            .after(LaserLabel::CalculateLaser),
+                    ),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::AimingLaser).with_system(hide_aiming_laser_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::FireLaser).with_system(reset_fire_laser_system),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(laser_timestep.chain(run_if_fire_laser_chain))
+                    .label(FireLaserLabel::Main)
+                    .with_system(
+                        instantiate_fire_laser_system
+                            .system()
+                            .label(FireLaserLabel::Instantiate),
+                    )
+                    .with_system(
+                        shoot_fire_laser_system
+                            .system()
+                            .label(FireLaserLabel::Shoot)
+                            .after(FireLaserLabel::Instantiate),
+                    )
+                    .with_system(
+                        drawing_system
+                            .system()
+                            .label(FireLaserLabel::Drawing)
+                            .after(FireLaserLabel::Shoot),
+                    )
+                    .with_system(
+                        hide_fire_laser_system
+                            .system()
+                            .label(FireLaserLabel::Hiding)
+                            .after(FireLaserLabel::Drawing),
+                    )
+                    .with_system(
+                        detect_end_fire_laser_system
+                            .system()
+                            .after(FireLaserLabel::Hiding)
+                            .label(FireLaserLabel::DetectEnd),
+                    ),
+            );
     }
 }
 
@@ -89,11 +81,21 @@ pub enum LaserLabel {
     DrawLaser,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FireLaserRes {
     pub amount: u32,
     pub shot: u32,
 }
 
+impl Default for FireLaserRes {
+    fn default() -> Self {
+        Self {
+            amount: 100,
+            shot: 0,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Laser {
     origin: Vec2,
@@ -111,7 +113,7 @@ impl Default for Laser {
 }
 
 #[derive(Component)]
-struct Aiming;
+pub struct Aiming;
 #[derive(Component)]
 struct Fire;
 #[derive(Component)]
@@ -119,11 +121,11 @@ struct Shooting {
     dir: Vec2,
 }
 
-fn aiming_startup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+fn aiming_startup(mut commands: Commands, theme_materials: Res<ThemeMaterialsRes>) {
     for _ in 0..AIMING_LASERS {
         commands
             .spawn_bundle(SpriteBundle {
-                material: materials.add(Color::rgb(1.0, 0.5, 0.0).into()),
+                material: theme_materials.aiming_laser.clone(),
                 visible: Visible {
                     is_visible: false,
                     is_transparent: false,
@@ -139,6 +141,8 @@ fn aiming_system(
     query_cannon: Query<(&GlobalTransform, &Transform), With<Cannon>>,
     query_collider: Query<(&Collider, &Transform)>,
     mut query_laser: Query<&mut Laser, With<Aiming>>,
+    board: Res<BoardConfigRes>,
+    settings: Res<GameSettingsRes>,
 ) {
     let (global_transform_cannon, transform_cannon) = query_cannon.single();
     let mut pos = global_transform_cannon.translation.xy() + transform_cannon.translation.xy();
@@ -150,8 +154,8 @@ fn aiming_system(
 
     let mut search_collide = true;
 
-    for mut laser in query_laser.iter_mut() {
-        if search_collide == true {
+    for (i, mut laser) in query_laser.iter_mut().enumerate() {
+        if search_collide == true && i < settings.aiming_preview {
             search_collide = false;
             laser.origin = pos;
             let mut min_distance_square = f32::MAX;
@@ -171,7 +175,7 @@ fn aiming_system(
 
             if search_collide == false {
                 laser.is_visible = true;
-                laser.destination = dir * MAX_LASER_LENGTH;
+                laser.destination = dir * board.max_laser_length();
             } else {
                 let a = normal.angle_between(dir);
                 let angle = 2.0 * a.abs() - std::f32::consts::PI;
@@ -204,7 +208,7 @@ fn drawing_system(mut query: Query<(&Laser, &mut Transform, &mut Sprite, &mut Vi
 }
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
-enum FireLaserLabel {
+pub enum FireLaserLabel {
     Main,
     Instantiate,
     Shoot,
@@ -215,18 +219,23 @@ enum FireLaserLabel {
 
 fn run_if_fire_laser_chain(
     In(should_run): In<ShouldRun>,
-    game_state: Res<GameStateRes>,
+    game_state: Res<State<GameState>>,
 ) -> ShouldRun {
-    match IS_FIRE_LASER_STATE(game_state) {
-        ShouldRun::Yes => should_run,
-        _ => ShouldRun::No,
+    if *game_state.current() == GameState::FireLaser {
+        should_run
+    } else {
+        ShouldRun::No
     }
 }
 
+fn reset_fire_laser_system(mut fire_laser: ResMut<FireLaserRes>) {
+    fire_laser.shot = 0;
+}
+
 fn instantiate_fire_laser_system(
     mut commands: Commands,
     mut fire_laser: ResMut<FireLaserRes>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    theme_materials: Res<ThemeMaterialsRes>,
     query_cannon: Query<(&GlobalTransform, &Transform), With<Cannon>>,
     mut query_laser: Query<(Entity, &mut Laser), (With<Fire>, Without<Shooting>)>,
 ) {
@@ -244,14 +253,7 @@ fn instantiate_fire_laser_system(
         } else {
             commands
                 .spawn_bundle(SpriteBundle {
-                    material: materials.add(
-                        if fire_laser.shot % 2 == 0 {
-                            Color::rgb(0.9, 0.1, 0.0)
-                        } else {
-                            Color::rgb(0.8, 0.2, 1.0)
-                        }
-                        .into(),
-                    ),
+                    material: theme_materials.fire_lasers[fire_laser.shot as usize % 2].clone(),
                     visible: Visible {
                         is_visible: false,
                         is_transparent: false,
@@ -273,6 +275,7 @@ fn shoot_fire_laser_system(
     mut commands: Commands,
     mut query_collider: Query<(&Collider, &Transform, Option<&mut Live>)>,
     mut query_laser: Query<(Entity, &mut Laser, &mut Shooting)>,
+    board: Res<BoardConfigRes>,
 ) {
     for (entity, mut laser, mut shooting) in query_laser.iter_mut() {
         let pos = laser.destination;
@@ -281,6 +284,10 @@ fn shoot_fire_laser_system(
         let mut min_distance = f32::MAX;
 
         for (collider, transform, o_live) in query_collider.iter_mut() {
+            //Destroyed elements stay until `destroy_block_system` despawns them, lasers pass through
+            if o_live.as_ref().map_or(false, |live| live.0 <= 0) {
+                continue;
+            }
             if let Some((hit, n)) = collider.ray_collide(transform, pos, dir) {
                 let distance = pos.distance_squared(hit);
 
@@ -303,7 +310,7 @@ fn shoot_fire_laser_system(
             }
         } else {
             laser.origin = laser.destination;
-            laser.destination += dir * MAX_LASER_LENGTH;
+            laser.destination += dir * board.max_laser_length();
             commands.entity(entity).remove::<Shooting>();
         }
     }
@@ -316,26 +323,22 @@ fn hide_fire_laser_system(mut query: Query<&mut Laser, (With<Aiming>, Without<Sh
 }
 
 fn detect_end_fire_laser_system(
-    mut fire_laser: ResMut<FireLaserRes>,
-    mut game_state: ResMut<GameStateRes>,
+    fire_laser: Res<FireLaserRes>,
+    mut game_state: ResMut<State<GameState>>,
     query_laser: Query<(), (With<Laser>, With<Shooting>)>,
 ) {
     if fire_laser.amount == fire_laser.shot && query_laser.iter().count() == 0 {
         info!("Shot: {}", fire_laser.shot);
-        fire_laser.shot = 0;
-        game_state.change(GameState::GenerateObstacle);
+        change_state(&mut game_state, GameState::GenerateObstacle);
     }
 }
 
 fn hide_aiming_laser_system(
-    game_state: Res<GameStateRes>,
     mut query: Query<(&mut Visible, &mut Laser), (With<Sprite>, With<Aiming>)>,
 ) {
-    if game_state.is_changed() {
-        for (mut visible, mut laser) in query.iter_mut() {
-            visible.is_visible = false;
-            laser.is_visible = false;
-        }
-        info!("Hide Lasers!")
+    for (mut visible, mut laser) in query.iter_mut() {
+        visible.is_visible = false;
+        laser.is_visible = false;
     }
+    info!("Hide Lasers!")
 }
@@ -1,11 +1,21 @@
-#![feature(adt_const_params)]
+pub mod action;
 pub mod click;
 pub mod collider2d;
 pub mod element;
 pub mod game_state;
+pub mod gamepad;
+pub mod grid;
+pub mod hud;
 pub mod laser;
+pub mod menu;
 pub mod player;
+pub mod practice;
+pub mod replay;
+pub mod save;
+pub mod settings;
+pub mod theme;
 pub mod timer;
+pub mod touch;
 pub mod window;
 
 use bevy::prelude::*;
@@ -15,15 +25,26 @@ pub struct MainCamera;
 
 fn main() {
     App::new()
+        .add_plugin(settings::SettingsPlugin)
         .add_plugin(window::WindowPlugin)
         .add_plugins(DefaultPlugins)
+        .add_plugin(theme::ThemePlugin)
         .add_startup_system(render_system.system())
+        .insert_resource(timer::TimeScaleRes::default())
+        .add_plugin(gamepad::GamepadPlugin)
+        .add_plugin(action::ActionPlugin)
+        .add_plugin(touch::TouchPlugin)
         .add_plugin(click::ClickablePlugin)
         .add_plugin(player::PlayerPlugin)
+        .add_plugin(grid::GridPlugin)
         .add_plugin(element::ElementPlugin)
         .add_plugin(laser::LaserPlugin)
         .add_plugin(game_state::GameStatePlugin)
-        .insert_resource(ClearColor(Color::rgb(0.1, 0.1, 0.1)))
+        .add_plugin(save::SavePlugin)
+        .add_plugin(replay::ReplayPlugin)
+        .add_plugin(practice::PracticePlugin)
+        .add_plugin(menu::MenuPlugin)
+        .add_plugin(hud::HudPlugin)
         .run();
 }
 
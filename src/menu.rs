@@ -0,0 +1,629 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    action::{Action, ActionsRes, BindingsRes, RebindRes},
+    element::AnimationMoveDown,
+    game_state::*,
+    laser::{Aiming, FireLaserRes, Laser, AIMING_LASERS},
+    player::{AimSettingsRes, Player, SNAP_STEP_OPTIONS},
+    practice::PracticeRes,
+    replay::ReplayRes,
+    save::{self, restore_snapshot, BoardSnapshot, SaveRes},
+    settings::{Difficulty, GameSettingsRes, AIMING_PREVIEW_OPTIONS, VOLUME_OPTIONS},
+    theme::{Theme, ThemeMaterialsRes, ThemeRes, ThemedText},
+    window::{BoardConfigRes, DisplayMode, DisplaySettingsRes, FRAME_RATE_OPTIONS},
+};
+
+pub struct MenuPlugin;
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StartGameEvent>()
+            .add_startup_system(start_system)
+            .add_startup_system(spawn_pause_button_system)
+            .add_system(button_color_system)
+            .add_system(toggle_pause_system)
+            .add_system(menu_button_system)
+            .add_system(binding_button_system)
+            .add_system(binding_label_system)
+            .add_system(setting_button_system)
+            .add_system(setting_label_system)
+            .add_system(start_game_system)
+            .add_system_set(
+                SystemSet::on_enter(GameState::MainMenu).with_system(spawn_main_menu_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Settings).with_system(spawn_settings_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(spawn_game_over_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused).with_system(spawn_pause_menu_system),
+            );
+
+        for state in [
+            GameState::MainMenu,
+            GameState::Settings,
+            GameState::GameOver,
+            GameState::Paused,
+        ] {
+            app.add_system_set(SystemSet::on_exit(state).with_system(despawn_menu_screen_system));
+        }
+    }
+}
+
+///Starts a run. A new game without seed uses a random one.
+pub enum StartGameEvent {
+    New { mode: GameMode, seed: Option<u64> },
+    Continue,
+}
+
+#[derive(Component)]
+struct PauseButton;
+
+///Starts rebinding the action, shows its bindings as label
+#[derive(Component)]
+struct BindingButton(Action);
+
+///Toggles an option in the settings screen
+#[derive(Component, Clone, Copy)]
+enum SettingButton {
+    MirroredAim,
+    SnapSteps,
+    DisplayMode,
+    Vsync,
+    FrameRate,
+    Difficulty,
+    AimingPreview,
+    Theme,
+    Volume,
+}
+
+impl SettingButton {
+    const ALL: [SettingButton; 9] = [
+        SettingButton::Difficulty,
+        SettingButton::MirroredAim,
+        SettingButton::SnapSteps,
+        SettingButton::AimingPreview,
+        SettingButton::Theme,
+        SettingButton::DisplayMode,
+        SettingButton::Vsync,
+        SettingButton::FrameRate,
+        SettingButton::Volume,
+    ];
+
+    fn label(
+        self,
+        aim_settings: &AimSettingsRes,
+        display: &DisplaySettingsRes,
+        game_settings: &GameSettingsRes,
+        theme: &ThemeRes,
+    ) -> String {
+        match self {
+            SettingButton::MirroredAim => format!(
+                "Mirrored Aim: {}",
+                if aim_settings.mirror_below {
+                    "On"
+                } else {
+                    "Off"
+                }
+            ),
+            SettingButton::SnapSteps => match aim_settings.snap_steps {
+                Some(steps) => format!("Angle Snap: {} Steps", steps),
+                None => "Angle Snap: Off".to_string(),
+            },
+            SettingButton::DisplayMode => format!("Window: {:?}", display.mode),
+            SettingButton::Vsync => format!("Vsync: {}", if display.vsync { "On" } else { "Off" }),
+            SettingButton::FrameRate => match display.frame_rate {
+                Some(frame_rate) => format!("Frame Rate: {}", frame_rate),
+                None => "Frame Rate: Unlimited".to_string(),
+            },
+            SettingButton::Difficulty => format!("Difficulty: {:?}", game_settings.difficulty),
+            SettingButton::AimingPreview => match game_settings.aiming_preview {
+                AIMING_LASERS => "Aim Preview: Full".to_string(),
+                segments => format!("Aim Preview: {} Segments", segments),
+            },
+            SettingButton::Theme => format!("Theme: {:?}", theme.0),
+            SettingButton::Volume => format!("Volume: {:.0}%", game_settings.volume * 100.0),
+        }
+    }
+}
+
+///The option after `current`, the last one is followed by the first one
+fn next_option<T: PartialEq + Copy>(options: &[T], current: T) -> T {
+    let next = options
+        .iter()
+        .position(|option| *option == current)
+        .map_or(0, |i| (i + 1) % options.len());
+    options[next]
+}
+
+///Root node of a full screen menu, despawned when its state is left
+#[derive(Component)]
+pub struct MenuScreen;
+
+#[derive(Component, Clone, Copy)]
+pub enum MenuButton {
+    Continue,
+    NewGame(GameMode),
+    Settings,
+    ResetBindings,
+    Back,
+    Resume,
+    Restart,
+    MainMenu,
+    Quit,
+}
+
+///Menu buttons react on the release of `Action::Select`, so the release is not handled by the game as well.
+pub fn is_button_released(interaction: &Interaction, actions: &ActionsRes) -> bool {
+    *interaction == Interaction::Hovered && actions.just_released(Action::Select)
+}
+
+pub fn spawn_button<T: Component>(
+    parent: &mut ChildBuilder,
+    theme_materials: &ThemeMaterialsRes,
+    size: Size<Val>,
+    label: &str,
+    marker: T,
+) {
+    spawn_button_with_font_size(parent, theme_materials, size, label, 24.0, marker);
+}
+
+pub fn spawn_button_with_font_size<T: Component>(
+    parent: &mut ChildBuilder,
+    theme_materials: &ThemeMaterialsRes,
+    size: Size<Val>,
+    label: &str,
+    font_size: f32,
+    marker: T,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size,
+                margin: Rect::all(Val::Px(5.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: theme_materials.button.clone(),
+            ..Default::default()
+        })
+        .insert(marker)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        label,
+                        TextStyle {
+                            font: theme_materials.font.clone(),
+                            font_size,
+                            color: theme_materials.text,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ThemedText);
+        });
+}
+
+///Replaces the text of a button spawned by `spawn_button`
+pub fn set_button_label(children: &Children, query_text: &mut Query<&mut Text>, label: String) {
+    for child in children.iter() {
+        if let Ok(mut text) = query_text.get_mut(*child) {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+///Full screen menu with a title above one button per entry. Returns the root node.
+pub fn spawn_menu_screen(
+    commands: &mut Commands,
+    theme_materials: &ThemeMaterialsRes,
+    title: &str,
+    buttons: &[(&str, MenuButton)],
+) -> Entity {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: theme_materials.menu_background.clone(),
+            ..Default::default()
+        })
+        .insert(MenuScreen)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        title,
+                        TextStyle {
+                            font: theme_materials.font.clone(),
+                            font_size: 40.0,
+                            color: theme_materials.text,
+                        },
+                        TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..Default::default()
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(ThemedText);
+            let size = Size::new(Val::Px(200.0), Val::Px(50.0));
+            for (label, button) in buttons.iter() {
+                spawn_button(parent, theme_materials, size, label, *button);
+            }
+        })
+        .id()
+}
+
+///Replays start right away, everything else in the main menu
+fn start_system(
+    mut game_state: ResMut<State<GameState>>,
+    mut start_game_events: EventWriter<StartGameEvent>,
+    replay: Res<ReplayRes>,
+) {
+    if replay.is_playback() {
+        start_game_events.send(StartGameEvent::New {
+            mode: replay.replay.mode,
+            seed: Some(replay.replay.seed),
+        });
+    } else {
+        change_state(&mut game_state, GameState::MainMenu);
+    }
+}
+
+fn spawn_pause_button_system(mut commands: Commands, theme_materials: Res<ThemeMaterialsRes>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(0.0),
+                    top: Val::Px(0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: theme_materials.menu_background.clone(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            spawn_button(
+                parent,
+                &theme_materials,
+                Size::new(Val::Px(40.0), Val::Px(40.0)),
+                "II",
+                PauseButton,
+            );
+        });
+}
+
+fn button_color_system(
+    theme_materials: Res<ThemeMaterialsRes>,
+    mut query: Query<
+        (&Interaction, &mut Handle<ColorMaterial>),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    for (interaction, mut material) in query.iter_mut() {
+        *material = match *interaction {
+            Interaction::Clicked => theme_materials.button_pressed.clone(),
+            Interaction::Hovered => theme_materials.button_hovered.clone(),
+            Interaction::None => theme_materials.button.clone(),
+        };
+    }
+}
+
+fn toggle_pause_system(
+    mut game_state: ResMut<State<GameState>>,
+    actions: Res<ActionsRes>,
+    query: Query<&Interaction, With<PauseButton>>,
+) {
+    let pause_button_released = query
+        .iter()
+        .any(|interaction| is_button_released(interaction, &actions));
+
+    if actions.just_pressed(Action::Pause) || pause_button_released {
+        if is_paused(&game_state) {
+            resume(&mut game_state);
+        } else if game_state.current().is_in_game() {
+            pause(&mut game_state);
+        }
+    }
+}
+
+fn spawn_main_menu_system(
+    mut commands: Commands,
+    theme_materials: Res<ThemeMaterialsRes>,
+    save: Res<SaveRes>,
+) {
+    let mut buttons = Vec::new();
+    if save.loaded.is_some() {
+        buttons.push(("Continue", MenuButton::Continue));
+    }
+    buttons.push(("Classic", MenuButton::NewGame(GameMode::Classic)));
+    buttons.push(("Endless", MenuButton::NewGame(GameMode::Endless)));
+    buttons.push(("Practice", MenuButton::NewGame(GameMode::Practice)));
+    buttons.push(("Settings", MenuButton::Settings));
+    buttons.push(("Quit", MenuButton::Quit));
+
+    spawn_menu_screen(&mut commands, &theme_materials, "Bevy Laser", &buttons);
+}
+
+fn spawn_settings_system(
+    mut commands: Commands,
+    theme_materials: Res<ThemeMaterialsRes>,
+    bindings: Res<BindingsRes>,
+    aim_settings: Res<AimSettingsRes>,
+    display: Res<DisplaySettingsRes>,
+    game_settings: Res<GameSettingsRes>,
+    theme: Res<ThemeRes>,
+) {
+    let screen = spawn_menu_screen(&mut commands, &theme_materials, "Settings", &[]);
+    commands.entity(screen).with_children(|parent| {
+        let size = Size::new(Val::Percent(95.0), Val::Px(30.0));
+        for setting in SettingButton::ALL {
+            spawn_button_with_font_size(
+                parent,
+                &theme_materials,
+                size,
+                &setting.label(&aim_settings, &display, &game_settings, &theme),
+                16.0,
+                setting,
+            );
+        }
+        for action in Action::REBINDABLE {
+            spawn_button_with_font_size(
+                parent,
+                &theme_materials,
+                size,
+                &bindings.label(action),
+                16.0,
+                BindingButton(action),
+            );
+        }
+        let size = Size::new(Val::Px(200.0), Val::Px(50.0));
+        spawn_button(
+            parent,
+            &theme_materials,
+            size,
+            "Reset Controls",
+            MenuButton::ResetBindings,
+        );
+        spawn_button(parent, &theme_materials, size, "Back", MenuButton::Back);
+    });
+}
+
+fn spawn_game_over_system(
+    mut commands: Commands,
+    theme_materials: Res<ThemeMaterialsRes>,
+    turn: Res<TurnRes>,
+) {
+    spawn_menu_screen(
+        &mut commands,
+        &theme_materials,
+        &format!("Game Over\nTurn {}", turn.0),
+        &[("Main Menu", MenuButton::MainMenu)],
+    );
+}
+
+fn spawn_pause_menu_system(mut commands: Commands, theme_materials: Res<ThemeMaterialsRes>) {
+    spawn_menu_screen(
+        &mut commands,
+        &theme_materials,
+        "Paused",
+        &[
+            ("Resume", MenuButton::Resume),
+            ("Restart", MenuButton::Restart),
+            ("Quit", MenuButton::Quit),
+        ],
+    );
+}
+
+fn despawn_menu_screen_system(mut commands: Commands, query: Query<Entity, With<MenuScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn menu_button_system(
+    mut game_state: ResMut<State<GameState>>,
+    mut start_game_events: EventWriter<StartGameEvent>,
+    mut app_exit_events: EventWriter<AppExit>,
+    mut bindings: ResMut<BindingsRes>,
+    mode: Res<GameModeRes>,
+    actions: Res<ActionsRes>,
+    query_buttons: Query<(&Interaction, &MenuButton)>,
+) {
+    let pressed = query_buttons
+        .iter()
+        .find(|(interaction, _)| is_button_released(*interaction, &actions))
+        .map(|(_, button)| *button);
+
+    match pressed {
+        Some(MenuButton::Continue) => start_game_events.send(StartGameEvent::Continue),
+        Some(MenuButton::NewGame(mode)) => {
+            start_game_events.send(StartGameEvent::New { mode, seed: None })
+        }
+        Some(MenuButton::Settings) => change_state(&mut game_state, GameState::Settings),
+        Some(MenuButton::ResetBindings) => {
+            *bindings = BindingsRes::default();
+        }
+        Some(MenuButton::Back) | Some(MenuButton::MainMenu) => {
+            change_state(&mut game_state, GameState::MainMenu)
+        }
+        Some(MenuButton::Resume) => resume(&mut game_state),
+        Some(MenuButton::Restart) => start_game_events.send(StartGameEvent::New {
+            mode: mode.0,
+            seed: None,
+        }),
+        Some(MenuButton::Quit) => app_exit_events.send(AppExit),
+        None => {}
+    }
+}
+
+fn setting_button_system(
+    mut aim_settings: ResMut<AimSettingsRes>,
+    mut display: ResMut<DisplaySettingsRes>,
+    mut game_settings: ResMut<GameSettingsRes>,
+    mut theme: ResMut<ThemeRes>,
+    actions: Res<ActionsRes>,
+    query_buttons: Query<(&Interaction, &SettingButton)>,
+) {
+    let pressed = query_buttons
+        .iter()
+        .find(|(interaction, _)| is_button_released(*interaction, &actions))
+        .map(|(_, setting)| *setting);
+
+    match pressed {
+        Some(SettingButton::MirroredAim) => aim_settings.mirror_below = !aim_settings.mirror_below,
+        Some(SettingButton::SnapSteps) => {
+            aim_settings.snap_steps = next_option(&SNAP_STEP_OPTIONS, aim_settings.snap_steps)
+        }
+        Some(SettingButton::DisplayMode) => {
+            display.mode = next_option(&DisplayMode::ALL, display.mode)
+        }
+        Some(SettingButton::Vsync) => display.vsync = !display.vsync,
+        Some(SettingButton::FrameRate) => {
+            display.frame_rate = next_option(&FRAME_RATE_OPTIONS, display.frame_rate)
+        }
+        Some(SettingButton::Difficulty) => {
+            game_settings.difficulty = next_option(&Difficulty::ALL, game_settings.difficulty)
+        }
+        Some(SettingButton::AimingPreview) => {
+            game_settings.aiming_preview =
+                next_option(&AIMING_PREVIEW_OPTIONS, game_settings.aiming_preview)
+        }
+        Some(SettingButton::Theme) => theme.0 = next_option(&Theme::ALL, theme.0),
+        Some(SettingButton::Volume) => {
+            game_settings.volume = next_option(&VOLUME_OPTIONS, game_settings.volume)
+        }
+        None => {}
+    }
+}
+
+fn setting_label_system(
+    aim_settings: Res<AimSettingsRes>,
+    display: Res<DisplaySettingsRes>,
+    game_settings: Res<GameSettingsRes>,
+    theme: Res<ThemeRes>,
+    query_buttons: Query<(&SettingButton, &Children)>,
+    mut query_text: Query<&mut Text>,
+) {
+    if !aim_settings.is_changed()
+        && !display.is_changed()
+        && !game_settings.is_changed()
+        && !theme.is_changed()
+    {
+        return;
+    }
+    for (setting, children) in query_buttons.iter() {
+        set_button_label(
+            children,
+            &mut query_text,
+            setting.label(&aim_settings, &display, &game_settings, &theme),
+        );
+    }
+}
+
+fn binding_button_system(
+    mut rebind: ResMut<RebindRes>,
+    actions: Res<ActionsRes>,
+    query_buttons: Query<(&Interaction, &BindingButton)>,
+) {
+    if let Some((_, binding_button)) = query_buttons
+        .iter()
+        .find(|(interaction, _)| is_button_released(*interaction, &actions))
+    {
+        rebind.0 = Some(binding_button.0);
+    }
+}
+
+fn binding_label_system(
+    bindings: Res<BindingsRes>,
+    rebind: Res<RebindRes>,
+    query_buttons: Query<(&BindingButton, &Children)>,
+    mut query_text: Query<&mut Text>,
+) {
+    if !bindings.is_changed() && !rebind.is_changed() {
+        return;
+    }
+    for (binding_button, children) in query_buttons.iter() {
+        let label = if rebind.0 == Some(binding_button.0) {
+            format!("{:?}: press a button", binding_button.0)
+        } else {
+            bindings.label(binding_button.0)
+        };
+        set_button_label(children, &mut query_text, label);
+    }
+}
+
+///Sets up the board for a new or a continued game
+#[allow(clippy::too_many_arguments)]
+fn start_game_system(
+    mut commands: Commands,
+    mut start_game_events: EventReader<StartGameEvent>,
+    mut game_state: ResMut<State<GameState>>,
+    mut save: ResMut<SaveRes>,
+    mut practice: ResMut<PracticeRes>,
+    theme_materials: Res<ThemeMaterialsRes>,
+    mut mode: ResMut<GameModeRes>,
+    mut turn: ResMut<TurnRes>,
+    mut rng: ResMut<RngRes>,
+    mut fire_laser: ResMut<FireLaserRes>,
+    query_elements: Query<Entity, With<AnimationMoveDown>>,
+    query_fire_lasers: Query<Entity, (With<Laser>, Without<Aiming>)>,
+    mut query_player: Query<&mut Transform, With<Player>>,
+    config: Res<BoardConfigRes>,
+) {
+    let (board, next_state) = match start_game_events.iter().last() {
+        Some(StartGameEvent::New { mode, seed }) => {
+            let rng = seed.map_or_else(RngRes::default, RngRes::from_seed);
+            save.loaded = None;
+            save::remove_save_file();
+            (
+                BoardSnapshot::new_game(*mode, rng, &config),
+                GameState::GenerateObstacle,
+            )
+        }
+        Some(StartGameEvent::Continue) => match save.loaded.take() {
+            Some(board) => (board, GameState::AimingLaser),
+            None => return,
+        },
+        None => return,
+    };
+
+    restore_snapshot(
+        &board,
+        &mut commands,
+        &theme_materials,
+        &query_elements,
+        &mut query_player.single_mut(),
+        &mut mode,
+        &mut turn,
+        &mut rng,
+        &mut fire_laser,
+        &config,
+    );
+    for entity in query_fire_lasers.iter() {
+        commands.entity(entity).despawn();
+    }
+    practice.history.clear();
+    save.snapshot = None;
+
+    info!("Start {:?} game at turn {}", mode.0, turn.0);
+    change_state(&mut game_state, next_state);
+}
@@ -1,15 +1,26 @@
 use std::f32::consts::PI;
 
-use bevy::{ecs::schedule::ShouldRun, math::Vec3Swizzles, prelude::*};
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    click::{self, Clicked},
+    action::{Action, ActionsRes},
+    click::{self, ClickEvent, DragEnd, DragMove},
     game_state::*,
-    window::{get_3d_from_cord, COLUMNS, HEIGHT, ROWS, SIZE, SIZE_MULTIPLIER, WIDTH},
+    replay::run_if_manual_input_chain,
+    theme::ThemeMaterialsRes,
+    timer::TimeScaleRes,
+    window::BoardConfigRes,
 };
 
 pub const MAX_ANGLE: f32 = PI / 2.5;
-//pub const MAX_STEPS: f32 = (6.0) / 2.0;
+///Choices for `AimSettingsRes::snap_steps`
+pub const SNAP_STEP_OPTIONS: [Option<u32>; 4] = [None, Some(3), Some(6), Some(12)];
+
+///Radians per second, while `Action::AimLeft` or `Action::AimRight` is pressed
+pub const ROTATION_SPEED: f32 = PI / 2.0;
+///Radians per second, while `Action::FineAim` is pressed as well
+pub const FINE_ROTATION_SPEED: f32 = PI / 16.0;
 
 #[derive(Component)]
 pub struct Player;
@@ -31,62 +42,125 @@ pub struct PossiblePositionsAnimation {
 #[derive(Component)]
 pub struct Cannon;
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AimSettingsRes {
+    ///Aiming below the cannon points it to the opposite direction
+    pub mirror_below: bool,
+    ///Steps between straight up and `MAX_ANGLE`, the cannon angle snaps to
+    pub snap_steps: Option<u32>,
+    ///Scales pointer and stick movement, while `Action::FineAim` is pressed
+    pub fine_aim_sensitivity: f32,
+}
+
+impl Default for AimSettingsRes {
+    fn default() -> Self {
+        Self {
+            mirror_below: false,
+            snap_steps: None,
+            fine_aim_sensitivity: 0.2,
+        }
+    }
+}
+
+///Cannon angle before snapping. All aiming inputs change it, `apply_aim_system` rotates the cannon.
+#[derive(Default)]
+pub struct AimRes {
+    pub angle: f32,
+    ///Last angle the pointer or stick aimed at, fine aiming moves relative to it
+    last_aim_angle: Option<f32>,
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub enum PlayerLabel {
+    Aim,
+}
+
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(startup_system)
+        app.init_resource::<AimSettingsRes>()
+            .insert_resource(AimRes::default())
+            .add_startup_system(startup_system)
             .add_system_set(
-                SystemSet::new()
-                    .with_run_criteria(IS_AIMING_LASER_STATE)
-                    .with_system(cannon_mouse_rotation_system)
+                SystemSet::on_enter(GameState::AimingLaser).with_system(sync_aim_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::AimingLaser)
                     //.with_system(select_possible_position_system)
                     .with_system(animate_selected_possible_position_system)
-                    .with_system(set_player_move_position_system)
-                    .with_system(player_start_shoot_system),
+                    .with_system(set_player_move_position_system),
             )
             .add_system_set(
                 SystemSet::new()
-                    .with_run_criteria(IS_MOVE_PLAYER_STATE)
-                    .with_system(player_movement_system),
+                    .with_run_criteria(run_if_aiming_laser.chain(run_if_manual_input_chain))
+                    .with_system(cannon_aim_system.label(PlayerLabel::Aim))
+                    .with_system(cannon_rotation_system.label(PlayerLabel::Aim))
+                    .with_system(apply_aim_system.after(PlayerLabel::Aim))
+                    .with_system(player_start_shoot_system)
+                    .with_system(fire_system)
+                    .with_system(select_position_system)
+                    .with_system(drag_player_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::MovePlayer).with_system(player_movement_system),
             );
     }
 }
 
-pub fn startup_system(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+///Like `SystemSet::on_update(GameState::AimingLaser)`, but can be chained with other run criteria
+pub fn run_if_aiming_laser(game_state: Res<State<GameState>>) -> ShouldRun {
+    if *game_state.current() == GameState::AimingLaser {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+pub fn startup_system(
+    mut commands: Commands,
+    theme_materials: Res<ThemeMaterialsRes>,
+    board: Res<BoardConfigRes>,
+) {
     println!("Spawn Player!");
+    let size = board.size();
 
     //Player Position Pattern
-    for i in 1..(COLUMNS as u16 - 1) {
+    for i in 1..(board.columns as u16 - 1) {
         let i = i as f32;
         commands
             .spawn_bundle(SpriteBundle {
-                material: materials.add(Color::rgb(0.2, 0.2, 0.2).into()),
+                material: theme_materials.position.clone(),
                 transform: Transform {
-                    translation: get_3d_from_cord(i, ROWS - 1.0, 0.0).into(),
+                    translation: board
+                        .get_3d_from_cord(i, board.rows as f32 - 1.0, 0.0)
+                        .into(),
                     ..Default::default()
                 },
-                sprite: Sprite::new((SIZE * 0.9, SIZE * 0.9).into()),
+                sprite: Sprite::new((size * 0.9, size * 0.9).into()),
                 ..Default::default()
             })
             .insert(PossiblePositions)
-            .insert(click::Clickable {
-                size: Vec2::new(SIZE, SIZE),
-                active: true,
-            });
+            .insert(click::Clickable::new(click::ClickShape::Rect(Vec2::new(
+                size, size,
+            ))));
     }
 
     //Player
     commands
         .spawn_bundle(SpriteBundle {
-            material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+            material: theme_materials.player.clone(),
             transform: Transform {
-                translation: get_3d_from_cord(COLUMNS / 2.0 - 0.5, ROWS - 2.0, 2.0).into(),
+                translation: player_start(&board),
                 ..Default::default()
             },
-            sprite: Sprite::new((SIZE, SIZE).into()),
+            sprite: Sprite::new((size, size).into()),
             ..Default::default()
         })
         .insert(Player)
+        .insert(click::Clickable::new(click::ClickShape::Rect(Vec2::new(
+            size, size,
+        ))))
+        .insert(click::Draggable { snap_to_grid: true })
         .insert(PlayerMoveAnimation {
             destination: Vec3::ZERO,
             at_destination: true,
@@ -102,75 +176,253 @@ pub fn startup_system(mut commands: Commands, mut materials: ResMut<Assets<Color
                 .insert(Cannon)
                 .with_children(|parent| {
                     parent.spawn_bundle(SpriteBundle {
-                        material: materials.add(Color::rgb(0.0, 0.0, 1.0).into()),
-                        transform: Transform::from_xyz(0.0, SIZE, -1.0),
-                        sprite: Sprite::new((SIZE / 2.0, SIZE).into()),
+                        material: theme_materials.cannon.clone(),
+                        transform: Transform::from_xyz(0.0, size, -1.0),
+                        sprite: Sprite::new((size / 2.0, size).into()),
                         ..Default::default()
                     });
                 });
         });
 }
 
-pub fn cannon_mouse_rotation_system(
-    mut query: Query<(&Cannon, &GlobalTransform, &mut Transform)>,
-    mut cursor_moved_events: EventReader<CursorMoved>,
+///Position of the player at the start of a game, in the middle of the row above the possible positions
+pub fn player_start(board: &BoardConfigRes) -> Vec3 {
+    board
+        .get_3d_from_cord((board.columns / 2) as f32, board.rows as f32 - 2.0, 2.0)
+        .into()
+}
+
+///Rotation of the cannon, as used to calculate the laser direction
+pub fn cannon_angle(transform: &Transform) -> f32 {
+    let (axis, angle) = transform.rotation.to_axis_angle();
+    axis.z * angle
+}
+
+///Column of the board, the transform is in
+pub fn column_of(board: &BoardConfigRes, transform: &Transform) -> u32 {
+    let (x, y, z) = transform.translation.into();
+    board.get_cord_from_3d(x, y, z).0.round() as u32
+}
+
+///Rotates the cannon angle by `delta` without leaving `MAX_ANGLE`
+pub fn rotate_angle(angle: f32, delta: f32) -> f32 {
+    (angle + delta).clamp(-MAX_ANGLE, MAX_ANGLE)
+}
+
+///Cannon angle for the aiming direction, `None` if the direction does not define one.
+///Directions below the cannon are mirrored like pulling back a slingshot, or clamped to the nearest limit.
+pub fn aim_angle(direction: Vec2, mirror_below: bool) -> Option<f32> {
+    let direction = if mirror_below && direction.y < 0.0 {
+        -direction
+    } else {
+        direction
+    };
+    //Straight down is as close to the left as to the right limit
+    if direction.x == 0.0 && direction.y <= 0.0 {
+        return None;
+    }
+    let angle = Vec2::Y.angle_between(direction);
+    if angle.is_nan() {
+        None
+    } else {
+        Some(angle.clamp(-MAX_ANGLE, MAX_ANGLE))
+    }
+}
+
+///Rounds the angle to the nearest of `steps` steps between straight up and `MAX_ANGLE`
+pub fn snap_angle(angle: f32, steps: u32) -> f32 {
+    if steps == 0 {
+        return angle;
+    }
+    let step = MAX_ANGLE / steps as f32;
+    (angle / step).round() * step
+}
+
+///Continues aiming from the current cannon rotation, e.g. after a replay or a restored game
+fn sync_aim_system(mut aim: ResMut<AimRes>, query: Query<&Transform, With<Cannon>>) {
+    aim.angle = cannon_angle(query.single());
+    aim.last_aim_angle = None;
+}
+
+pub fn cannon_aim_system(
+    mut aim: ResMut<AimRes>,
+    actions: Res<ActionsRes>,
+    aim_settings: Res<AimSettingsRes>,
 ) {
-    if let Some(cursor_move) = cursor_moved_events.iter().next() {
-        let (_, global_transform, mut transform) = query.single_mut();
+    if let Some(target) = actions
+        .aim
+        .and_then(|direction| aim_angle(direction, aim_settings.mirror_below))
+    {
+        aim.angle = match aim.last_aim_angle {
+            Some(last) if actions.pressed(Action::FineAim) => rotate_angle(
+                aim.angle,
+                (target - last) * aim_settings.fine_aim_sensitivity,
+            ),
+            _ => target,
+        };
+        aim.last_aim_angle = Some(target);
+    }
+}
 
-        let cursor_pos: Vec2 = cursor_move.position;
-        let cannon_pos: Vec2 =
-            global_transform.translation.xy() + Vec2::new(WIDTH / 2.0, HEIGHT / 2.0);
-        let vec = cursor_pos - cannon_pos;
-        let mut angle = Vec2::new(0.0, 1.0).angle_between(vec);
+pub fn cannon_rotation_system(mut aim: ResMut<AimRes>, actions: Res<ActionsRes>, time: Res<Time>) {
+    let direction = match (
+        actions.pressed(Action::AimLeft),
+        actions.pressed(Action::AimRight),
+    ) {
+        (true, false) => 1.0,
+        (false, true) => -1.0,
+        _ => return,
+    };
+    let speed = if actions.pressed(Action::FineAim) {
+        FINE_ROTATION_SPEED
+    } else {
+        ROTATION_SPEED
+    };
+
+    aim.angle = rotate_angle(aim.angle, direction * speed * time.delta_seconds());
+}
+
+pub fn apply_aim_system(
+    aim: Res<AimRes>,
+    aim_settings: Res<AimSettingsRes>,
+    mut query: Query<&mut Transform, With<Cannon>>,
+) {
+    let angle = aim_settings
+        .snap_steps
+        .map_or(aim.angle, |steps| snap_angle(aim.angle, steps));
+    query.single_mut().rotation = Quat::from_rotation_z(angle);
+}
+
+pub fn fire_system(mut game_state: ResMut<State<GameState>>, actions: Res<ActionsRes>) {
+    if actions.just_pressed(Action::Fire) {
+        change_state(&mut game_state, GameState::FireLaser);
+    }
+}
+
+///Clicks the `PossiblePositions` of the selected column, so the player moves like on a mouse click
+pub fn select_position_system(
+    mut click_events: EventWriter<ClickEvent>,
+    actions: Res<ActionsRes>,
+    query_player: Query<&Transform, With<Player>>,
+    query_positions: Query<
+        (Entity, &Transform, &click::Clickable),
+        (With<PossiblePositions>, Without<Player>),
+    >,
+    board: Res<BoardConfigRes>,
+) {
+    let current = column_of(&board, query_player.single());
+    let column = if actions.just_pressed(Action::MoveLeft) {
+        current.checked_sub(1)
+    } else if actions.just_pressed(Action::MoveRight) {
+        Some(current + 1)
+    } else {
+        (1..=board.columns as u8)
+            .find(|column| actions.just_pressed(Action::Column(*column)))
+            .map(u32::from)
+    };
 
-        if !angle.is_nan() {
-            //angle = ((angle / MAX_ANGLE * MAX_STEPS).floor() - 0.5) * (MAX_ANGLE / MAX_STEPS);
-            angle = angle.clamp(-MAX_ANGLE, MAX_ANGLE);
+    if let Some(column) = column.filter(|column| *column != current) {
+        click_possible_position(&mut click_events, &query_positions, &board, column);
+    }
+}
 
-            transform.rotation = Quat::from_rotation_z(angle);
+///The player is dragged along its row and moves to the `PossiblePositions` it is dropped on
+pub fn drag_player_system(
+    mut click_events: EventWriter<ClickEvent>,
+    mut drag_move_events: EventReader<DragMove>,
+    mut drag_end_events: EventReader<DragEnd>,
+    mut query_player: Query<(Entity, &mut Transform), With<Player>>,
+    query_positions: Query<
+        (Entity, &Transform, &click::Clickable),
+        (With<PossiblePositions>, Without<Player>),
+    >,
+    board: Res<BoardConfigRes>,
+) {
+    let (player, mut transform) = query_player.single_mut();
+    for drag_move in drag_move_events
+        .iter()
+        .filter(|event| event.entity == player)
+    {
+        transform.translation.x = drag_move.position.x;
+    }
+    for drag_end in drag_end_events
+        .iter()
+        .filter(|event| event.entity == player)
+    {
+        let column = column_of(&board, &transform);
+        if !click_possible_position(&mut click_events, &query_positions, &board, column) {
+            //Dropped besides the possible positions
+            transform.translation.x -= drag_end.delta.x;
         }
     }
-    //FIXME Does not rotate, when mouse is under cannon. Solution: Abs value
+}
+
+///Moves the player to `column`, as if its `PossiblePositions` was clicked.
+///Returns `false`, if there is no active position in the column.
+pub fn click_possible_position(
+    click_events: &mut EventWriter<ClickEvent>,
+    query_positions: &Query<
+        (Entity, &Transform, &click::Clickable),
+        (With<PossiblePositions>, Without<Player>),
+    >,
+    board: &BoardConfigRes,
+    column: u32,
+) -> bool {
+    if let Some((entity, ..)) = query_positions
+        .iter()
+        .find(|(_, transform, clickable)| clickable.active && column_of(board, transform) == column)
+    {
+        click_events.send(ClickEvent {
+            entity,
+            button: Action::Select,
+        });
+        true
+    } else {
+        false
+    }
 }
 
 pub fn animate_selected_possible_position_system(
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    query: Query<
+    theme_materials: Res<ThemeMaterialsRes>,
+    mut query: Query<
         (
             Entity,
             //&PossiblePositionsAnimation,
             Option<&click::Selected>,
             Option<&click::Hovered>,
-            &Handle<ColorMaterial>,
+            &mut Handle<ColorMaterial>,
         ),
         With<PossiblePositions>,
     >,
 ) {
-    for (_entity, selected, hovered, material) in query.iter() {
-        if selected.is_some() {
+    for (_entity, selected, hovered, mut material) in query.iter_mut() {
+        let next = if selected.is_some() {
             if hovered.is_some() {
-                let _ = materials.set(material, Color::rgb(0.4, 0.2, 0.2).into());
+                &theme_materials.position_selected_hovered
             } else {
-                let _ = materials.set(material, Color::rgb(0.2, 0.4, 0.2).into());
+                &theme_materials.position_selected
             }
+        } else if hovered.is_some() {
+            &theme_materials.position_hovered
         } else {
-            if hovered.is_some() {
-                let _ = materials.set(material, Color::rgb(0.2, 0.2, 0.4).into());
-            } else {
-                let _ = materials.set(material, Color::rgb(0.2, 0.2, 0.2).into());
-            }
+            &theme_materials.position
+        };
+        //Only set on change, so the handle is not marked as changed every frame
+        if *material != *next {
+            *material = next.clone();
         }
         //TODO Add animation
     }
 }
 
 pub fn player_movement_system(
-    mut game_state: ResMut<GameStateRes>,
+    mut game_state: ResMut<State<GameState>>,
     mut query: Query<(&mut Transform, &mut PlayerMoveAnimation)>,
     delta_time: Res<Time>,
+    time_scale: Res<TimeScaleRes>,
+    board: Res<BoardConfigRes>,
 ) {
-    let max_length = SIZE_MULTIPLIER / 2.0 * delta_time.delta_seconds() * 50.0;
+    let max_length = board.size_multiplier / 2.0 * time_scale.delta_seconds(&delta_time) * 50.0;
     let max_length_squared = max_length * max_length;
 
     let (mut transform, mut player_move_animation) = query.single_mut();
@@ -183,45 +435,91 @@ pub fn player_movement_system(
             transform.translation += vec.normalize() * max_length;
         }
     } else {
-        game_state.change(GameState::AimingLaser);
+        change_state(&mut game_state, GameState::AimingLaser);
     }
 }
 
 pub fn set_player_move_position_system(
-    mut game_state: ResMut<GameStateRes>,
-    mut commands: Commands,
-    query_selectable: Query<(Entity, &Transform), (With<PossiblePositions>, With<Clicked>)>,
+    mut game_state: ResMut<State<GameState>>,
+    mut click_events: EventReader<ClickEvent>,
+    query_positions: Query<&Transform, With<PossiblePositions>>,
     mut query_player: Query<&mut PlayerMoveAnimation, With<Player>>,
+    board: Res<BoardConfigRes>,
 ) {
-    if let Ok((entity, transform)) = query_selectable.get_single() {
+    if let Some(transform) = click_events
+        .iter()
+        .filter(|event| event.button == Action::Select)
+        .find_map(|event| query_positions.get(event.entity).ok())
+    {
         let mut player_move_animation = query_player.single_mut();
         player_move_animation.at_destination = false;
         player_move_animation.destination =
-            (transform.translation.x, (-ROWS / 2.0 + 1.5) * SIZE, 2.0).into();
-
-        commands.entity(entity).remove::<Clicked>();
+            (transform.translation.x, player_start(&board).y, 2.0).into();
 
-        game_state.change(GameState::MovePlayer);
+        change_state(&mut game_state, GameState::MovePlayer);
     }
 }
 
 pub fn player_start_shoot_system(
-    mut game_state: ResMut<GameStateRes>,
-    mouse_buttons: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    mut game_state: ResMut<State<GameState>>,
+    actions: Res<ActionsRes>,
+    cursor_position: Res<click::CursorPositionRes>,
+    query_buttons: Query<&Interaction, With<Button>>,
+    query_player: Query<&click::Selected, With<Player>>,
+    board: Res<BoardConfigRes>,
 ) {
-    if let Some(vec2) = windows
-        .get_primary()
-        .expect("no primary window")
-        .cursor_position()
+    //Click on a menu button, or the player is dropped after a drag
+    if query_buttons
+        .iter()
+        .any(|interaction| *interaction != Interaction::None)
+        || !query_player.is_empty()
     {
-        if mouse_buttons.just_released(MouseButton::Left)
-            && vec2.x >= 0.0
-            && vec2.x <= WIDTH
-            && vec2.y >= SIZE * 3.0
-            && vec2.y <= HEIGHT
+        return;
+    }
+    //Board above the player positions
+    if let Some(vec2) = cursor_position.0 {
+        if actions.just_released(Action::Select)
+            && vec2.x.abs() <= board.width() / 2.0
+            && vec2.y >= -board.height() / 2.0 + board.size() * 3.0
+            && vec2.y <= board.height() / 2.0
         {
-            game_state.change(GameState::FireLaser);
+            change_state(&mut game_state, GameState::FireLaser);
         }
     }
 }
+
+#[test]
+fn test_rotate_angle() {
+    assert_eq!(rotate_angle(0.0, 0.1), 0.1);
+    assert_eq!(rotate_angle(MAX_ANGLE - 0.1, 0.5), MAX_ANGLE);
+    assert_eq!(rotate_angle(-MAX_ANGLE + 0.1, -0.5), -MAX_ANGLE);
+}
+
+#[test]
+fn test_snap_angle() {
+    let step = MAX_ANGLE / 4.0;
+    assert_eq!(snap_angle(0.4 * step, 4), 0.0);
+    assert_eq!(snap_angle(1.6 * step, 4), 2.0 * step);
+    assert_eq!(snap_angle(-MAX_ANGLE, 4), -MAX_ANGLE);
+    assert_eq!(snap_angle(0.3, 0), 0.3);
+}
+
+#[test]
+fn test_aim_angle() {
+    let left_below = Vec2::new(-1.0, -1.0);
+    let right_below = Vec2::new(1.0, -1.0);
+
+    assert_eq!(aim_angle(Vec2::Y, false), Some(0.0));
+    assert_eq!(aim_angle(Vec2::ZERO, false), None);
+    assert_eq!(aim_angle(-Vec2::Y, false), None);
+    assert_eq!(aim_angle(-Vec2::Y, true), Some(0.0));
+
+    assert_eq!(aim_angle(left_below, false), Some(MAX_ANGLE));
+    assert_eq!(aim_angle(right_below, false), Some(-MAX_ANGLE));
+    assert_eq!(aim_angle(-Vec2::X, false), Some(MAX_ANGLE));
+
+    let mirrored = aim_angle(left_below, true).unwrap();
+    assert!((mirrored + PI / 4.0).abs() < 1e-5);
+    let mirrored = aim_angle(right_below, true).unwrap();
+    assert!((mirrored - PI / 4.0).abs() < 1e-5);
+}
@@ -0,0 +1,199 @@
+use bevy::prelude::*;
+
+use crate::{
+    action::{Action, ActionsRes},
+    element::{AnimationMoveDown, Block, Bounce, Live, PowerupAddLaser, SpawnLive},
+    game_state::*,
+    laser::FireLaserRes,
+    player::Player,
+    replay::ReplayRes,
+    save::{capture_snapshot, restore_snapshot, BoardSnapshot, SaveRes},
+    theme::ThemeMaterialsRes,
+    window::BoardConfigRes,
+};
+
+///In `GameMode::Practice` every turn can be reverted with `Action::Undo`.
+pub struct PracticePlugin;
+impl Plugin for PracticePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PracticeRes::default())
+            .add_system_set(
+                SystemSet::on_enter(GameState::AimingLaser).with_system(practice_snapshot_system),
+            )
+            .add_system_set(SystemSet::on_update(GameState::AimingLaser).with_system(undo_system));
+    }
+}
+
+#[derive(Default)]
+pub struct PracticeRes {
+    ///Board at the start of every turn, the last one is the current turn
+    pub history: Vec<BoardSnapshot>,
+}
+
+fn practice_snapshot_system(
+    mut practice: ResMut<PracticeRes>,
+    mode: Res<GameModeRes>,
+    turn: Res<TurnRes>,
+    rng: Res<RngRes>,
+    fire_laser: Res<FireLaserRes>,
+    query_player: Query<&Transform, With<Player>>,
+    query_elements: Query<(
+        &AnimationMoveDown,
+        Option<&Block>,
+        Option<&PowerupAddLaser>,
+        Option<&Bounce>,
+        Option<&Live>,
+        Option<&SpawnLive>,
+    )>,
+) {
+    if mode.0 != GameMode::Practice {
+        return;
+    }
+    //Moving the player returns to this state without a new turn
+    if let Some(snapshot) = practice.history.last() {
+        if snapshot.turn.0 == turn.0 {
+            return;
+        }
+    }
+
+    let snapshot = capture_snapshot(
+        &mode,
+        &turn,
+        &rng,
+        &fire_laser,
+        query_player.single(),
+        &query_elements,
+    );
+    practice.history.push(snapshot);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn undo_system(
+    mut commands: Commands,
+    mut practice: ResMut<PracticeRes>,
+    mut save: ResMut<SaveRes>,
+    mut replay: ResMut<ReplayRes>,
+    theme_materials: Res<ThemeMaterialsRes>,
+    mut mode: ResMut<GameModeRes>,
+    mut turn: ResMut<TurnRes>,
+    mut rng: ResMut<RngRes>,
+    mut fire_laser: ResMut<FireLaserRes>,
+    actions: Res<ActionsRes>,
+    query_elements: Query<Entity, With<AnimationMoveDown>>,
+    mut query_player: Query<&mut Transform, With<Player>>,
+    config: Res<BoardConfigRes>,
+) {
+    if mode.0 != GameMode::Practice || !actions.just_pressed(Action::Undo) {
+        return;
+    }
+    if practice.history.len() < 2 {
+        info!("Nothing to undo");
+        return;
+    }
+
+    practice.history.pop();
+    let snapshot = practice.history.last().unwrap().clone();
+    restore_snapshot(
+        &snapshot,
+        &mut commands,
+        &theme_materials,
+        &query_elements,
+        &mut query_player.single_mut(),
+        &mut mode,
+        &mut turn,
+        &mut rng,
+        &mut fire_laser,
+        &config,
+    );
+    replay
+        .replay
+        .turns
+        .truncate(snapshot.turn.0.saturating_sub(1) as usize);
+    save.snapshot = Some(snapshot);
+    info!("Undo to turn {}", turn.0);
+}
+
+#[test]
+fn test_undo() {
+    use bevy::ecs::schedule::Stage;
+
+    use crate::{
+        element::ElementKind,
+        replay::{Replay, ReplayMode, ReplayTurn},
+        save::ElementSnapshot,
+    };
+
+    let config = BoardConfigRes::default();
+    //Turn `i` has `i` blocks and lasers
+    let history = (1..=3)
+        .map(|i| {
+            let mut snapshot =
+                BoardSnapshot::new_game(GameMode::Practice, RngRes::from_seed(42), &config);
+            snapshot.turn = TurnRes(i);
+            snapshot.fire_laser.amount = i;
+            snapshot.elements = (0..i)
+                .map(|x| ElementSnapshot {
+                    kind: ElementKind::Block,
+                    x: x as f32,
+                    y: 0.0,
+                    live: Some(1),
+                    spawn_live: Some(1),
+                })
+                .collect();
+            snapshot
+        })
+        .collect();
+
+    let mut world = World::default();
+    world.insert_resource(PracticeRes { history });
+    world.insert_resource(SaveRes::default());
+    world.insert_resource(ReplayRes {
+        mode: ReplayMode::Record,
+        replay: Replay {
+            turns: vec![
+                ReplayTurn {
+                    column: 0,
+                    angle: 0.0
+                };
+                3
+            ],
+            ..Replay::default()
+        },
+    });
+    world.insert_resource(ThemeMaterialsRes::without_assets());
+    world.insert_resource(GameModeRes(GameMode::Practice));
+    world.insert_resource(TurnRes(3));
+    world.insert_resource(RngRes::from_seed(42));
+    world.insert_resource(FireLaserRes::default());
+    world.insert_resource(ActionsRes::default());
+    world.insert_resource(config);
+    world.spawn().insert(Player).insert(Transform::default());
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(undo_system);
+
+    let mut undo = |world: &mut World| {
+        let mut actions = world.get_resource_mut::<ActionsRes>().unwrap();
+        actions.reset();
+        actions.press(Action::Undo);
+        stage.run(world);
+    };
+    let elements = |world: &mut World| {
+        world
+            .query_filtered::<Entity, With<AnimationMoveDown>>()
+            .iter(world)
+            .count()
+    };
+
+    for turn in [2, 1, 1] {
+        undo(&mut world);
+        assert_eq!(world.get_resource::<TurnRes>().unwrap().0, turn);
+        assert_eq!(world.get_resource::<FireLaserRes>().unwrap().amount, turn);
+        assert_eq!(elements(&mut world), turn as usize);
+        let replay = world.get_resource::<ReplayRes>().unwrap();
+        assert_eq!(replay.replay.turns.len(), turn as usize - 1);
+        assert_eq!(
+            world.get_resource::<PracticeRes>().unwrap().history.len(),
+            turn as usize
+        );
+    }
+}
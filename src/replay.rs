@@ -0,0 +1,268 @@
+use std::fs;
+
+use bevy::{app::AppExit, ecs::schedule::ShouldRun, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::Action,
+    click::{ClickEvent, Clickable},
+    game_state::*,
+    player::{cannon_angle, column_of, Cannon, Player, PossiblePositions},
+    timer::TimeScaleRes,
+    window::BoardConfigRes,
+};
+
+///Bump when `Replay` or `ReplayTurn` change, replays recorded with another version can not be played back
+pub const REPLAY_VERSION: u32 = 3;
+pub const REPLAY_PATH: &str = "replay.ron";
+
+///Records every run into `REPLAY_PATH`.
+///Start with `--replay <file>` to play a replay back, `--replay-speed <factor>` accelerates it.
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let replay = ReplayRes::from_args(std::env::args().collect());
+        if let ReplayMode::Playback { speed } = replay.mode {
+            //Replaces the board chosen by `WindowPlugin`, the replay only works on its own board
+            app.insert_resource(TimeScaleRes(speed))
+                .insert_resource(replay.replay.board);
+        }
+
+        app.insert_resource(replay)
+            .add_startup_system_to_stage(StartupStage::PostStartup, playback_startup_system)
+            .add_system_set(
+                SystemSet::on_enter(GameState::FireLaser).with_system(record_turn_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::AimingLaser).with_system(playback_system),
+            )
+            .add_system_to_stage(CoreStage::Last, write_replay_on_exit_system);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayMode {
+    Record,
+    Playback { speed: f32 },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub mode: GameMode,
+    pub seed: u64,
+    pub board: BoardConfigRes,
+    pub turns: Vec<ReplayTurn>,
+}
+
+///Input of the player in one turn
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct ReplayTurn {
+    ///Column of the player, when the laser was fired
+    pub column: u32,
+    pub angle: f32,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            mode: GameMode::Classic,
+            seed: 0,
+            board: BoardConfigRes::default(),
+            turns: Vec::new(),
+        }
+    }
+}
+
+pub struct ReplayRes {
+    pub mode: ReplayMode,
+    pub replay: Replay,
+}
+
+impl ReplayRes {
+    fn from_args(args: Vec<String>) -> Self {
+        let speed = args
+            .iter()
+            .position(|arg| arg == "--replay-speed")
+            .and_then(|i| args.get(i + 1))
+            .map_or(1.0, |speed| match speed.parse::<f32>() {
+                Ok(speed) if speed.is_finite() && speed > 0.0 => speed,
+                _ => {
+                    error!("Invalid replay speed {}, expected a number above 0", speed);
+                    1.0
+                }
+            });
+
+        if let Some(path) = args
+            .iter()
+            .position(|arg| arg == "--replay")
+            .and_then(|i| args.get(i + 1))
+        {
+            match read_replay_file(path) {
+                Ok(replay) => {
+                    info!("Play replay {} with {} turns", path, replay.turns.len());
+                    return Self {
+                        mode: ReplayMode::Playback { speed },
+                        replay,
+                    };
+                }
+                Err(err) => error!("Could not load replay {}: {}", path, err),
+            }
+        }
+
+        Self {
+            mode: ReplayMode::Record,
+            replay: Replay::default(),
+        }
+    }
+
+    pub fn is_playback(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playback { .. })
+    }
+}
+
+pub fn read_replay_file(path: &str) -> Result<Replay, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let replay = ron::from_str::<Replay>(&text).map_err(|err| err.to_string())?;
+    if replay.version != REPLAY_VERSION {
+        return Err(format!(
+            "version {} is not supported (expected {})",
+            replay.version, REPLAY_VERSION
+        ));
+    }
+    Ok(replay)
+}
+
+pub fn write_replay_file(path: &str, replay: &Replay) {
+    match ron::ser::to_string_pretty(replay, ron::ser::PrettyConfig::default()) {
+        Ok(text) => {
+            if let Err(err) = fs::write(path, text) {
+                error!("Could not write replay: {}", err);
+            }
+        }
+        Err(err) => error!("Could not serialize replay: {}", err),
+    }
+}
+
+///Disables the mouse input during playback
+pub fn run_if_manual_input_chain(
+    In(should_run): In<ShouldRun>,
+    replay: Res<ReplayRes>,
+) -> ShouldRun {
+    if replay.is_playback() {
+        ShouldRun::No
+    } else {
+        should_run
+    }
+}
+
+fn playback_startup_system(
+    replay: Res<ReplayRes>,
+    mut query: Query<&mut Clickable, With<PossiblePositions>>,
+) {
+    if replay.is_playback() {
+        for mut clickable in query.iter_mut() {
+            clickable.active = false;
+        }
+    }
+}
+
+///Stores the input of the turn, as soon as the laser is fired.
+///The cannon rotation is rebuilt from the stored angle, so recording and playback shoot in exactly the same direction.
+fn record_turn_system(
+    mode: Res<GameModeRes>,
+    turn: Res<TurnRes>,
+    rng: Res<RngRes>,
+    mut replay: ResMut<ReplayRes>,
+    board: Res<BoardConfigRes>,
+    query_player: Query<&Transform, (With<Player>, Without<Cannon>)>,
+    mut query_cannon: Query<&mut Transform, With<Cannon>>,
+) {
+    if replay.is_playback() {
+        return;
+    }
+
+    if turn.0 == 1 {
+        replay.replay = Replay {
+            version: REPLAY_VERSION,
+            mode: mode.0,
+            seed: rng.seed(),
+            board: *board,
+            turns: Vec::new(),
+        };
+    } else if replay.replay.turns.len() + 1 != turn.0 as usize {
+        //Resumed games can not be replayed from the seed
+        return;
+    }
+
+    let mut cannon_transform = query_cannon.single_mut();
+    let angle = cannon_angle(&cannon_transform);
+    cannon_transform.rotation = Quat::from_rotation_z(angle);
+
+    replay.replay.turns.push(ReplayTurn {
+        column: column_of(&board, query_player.single()),
+        angle,
+    });
+}
+
+fn playback_system(
+    mut click_events: EventWriter<ClickEvent>,
+    mut game_state: ResMut<State<GameState>>,
+    mut replay: ResMut<ReplayRes>,
+    turn: Res<TurnRes>,
+    board: Res<BoardConfigRes>,
+    query_player: Query<&Transform, (With<Player>, Without<Cannon>)>,
+    mut query_cannon: Query<&mut Transform, (With<Cannon>, Without<PossiblePositions>)>,
+    mut query_positions: Query<
+        (Entity, &Transform, &mut Clickable),
+        (With<PossiblePositions>, Without<Player>),
+    >,
+) {
+    if !replay.is_playback() {
+        return;
+    }
+
+    //Turns are counted from 1, the first generated row starts turn 1
+    let index = match turn.0.checked_sub(1) {
+        Some(index) => index as usize,
+        None => return,
+    };
+    let replay_turn = match replay.replay.turns.get(index) {
+        Some(replay_turn) => *replay_turn,
+        None => {
+            info!("Replay finished");
+            replay.mode = ReplayMode::Record;
+            for (_, _, mut clickable) in query_positions.iter_mut() {
+                clickable.active = true;
+            }
+            return;
+        }
+    };
+
+    if column_of(&board, query_player.single()) != replay_turn.column {
+        if let Some((entity, ..)) = query_positions
+            .iter_mut()
+            .find(|(_, transform, _)| column_of(&board, *transform) == replay_turn.column)
+        {
+            click_events.send(ClickEvent {
+                entity,
+                button: Action::Select,
+            });
+            return;
+        }
+        warn!("Replay column {} does not exist", replay_turn.column);
+    }
+
+    query_cannon.single_mut().rotation = Quat::from_rotation_z(replay_turn.angle);
+    change_state(&mut game_state, GameState::FireLaser);
+}
+
+fn write_replay_on_exit_system(replay: Res<ReplayRes>, mut app_exit_events: EventReader<AppExit>) {
+    if app_exit_events.iter().next().is_some()
+        && !replay.is_playback()
+        && !replay.replay.turns.is_empty()
+    {
+        write_replay_file(REPLAY_PATH, &replay.replay);
+    }
+}
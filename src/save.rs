@@ -0,0 +1,321 @@
+use std::fs;
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    element::{
+        self, AnimationMoveDown, Block, Bounce, ElementKind, Live, PowerupAddLaser, SpawnLive,
+    },
+    game_state::*,
+    laser::FireLaserRes,
+    player::{player_start, Player},
+    replay::ReplayRes,
+    theme::ThemeMaterialsRes,
+    window::BoardConfigRes,
+};
+
+///Bump when `SaveFile` or `BoardSnapshot` change, older saves are not offered to continue
+pub const SAVE_VERSION: u32 = 4;
+pub const SAVE_PATH: &str = "savegame.ron";
+
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SaveRes::default())
+            .add_startup_system(load_save_startup_system)
+            .add_system_set(
+                SystemSet::on_enter(GameState::AimingLaser).with_system(snapshot_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(remove_save_system),
+            )
+            .add_system_to_stage(CoreStage::Last, save_on_exit_system);
+    }
+}
+
+#[derive(Default)]
+pub struct SaveRes {
+    ///Board at the start of the current turn
+    pub snapshot: Option<BoardSnapshot>,
+    ///Board read from the save file, offered in the main menu
+    pub loaded: Option<BoardSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BoardSnapshot {
+    pub mode: GameMode,
+    pub turn: TurnRes,
+    pub rng: RngRes,
+    pub fire_laser: FireLaserRes,
+    pub player_x: f32,
+    pub elements: Vec<ElementSnapshot>,
+}
+
+impl BoardSnapshot {
+    ///Empty board
+    pub fn new_game(mode: GameMode, rng: RngRes, config: &BoardConfigRes) -> Self {
+        Self {
+            mode,
+            turn: TurnRes::default(),
+            rng,
+            fire_laser: FireLaserRes::default(),
+            player_x: player_start(config).x,
+            elements: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ElementSnapshot {
+    pub kind: ElementKind,
+    pub x: f32,
+    pub y: f32,
+    pub live: Option<i32>,
+    pub spawn_live: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    ///Saved games can only be continued on a board of the same size
+    config: BoardConfigRes,
+    board: BoardSnapshot,
+}
+
+pub fn read_save_file(config: &BoardConfigRes) -> Option<BoardSnapshot> {
+    let text = fs::read_to_string(SAVE_PATH).ok()?;
+    parse_save_file(&text, config)
+        .map_err(|err| warn!("{}", err))
+        .ok()
+}
+
+///Only a save of the current version and board size can be continued
+fn parse_save_file(text: &str, config: &BoardConfigRes) -> Result<BoardSnapshot, String> {
+    match ron::from_str::<SaveFile>(text) {
+        Ok(save_file) if save_file.version != SAVE_VERSION => Err(format!(
+            "Ignore save file with version {} (expected {})",
+            save_file.version, SAVE_VERSION
+        )),
+        Ok(save_file) if save_file.config != *config => Err(format!(
+            "Ignore save file of a {}x{} board",
+            save_file.config.columns, save_file.config.rows
+        )),
+        Ok(save_file) => Ok(save_file.board),
+        Err(err) => Err(format!("Could not read save file: {}", err)),
+    }
+}
+
+pub fn write_save_file(board: &BoardSnapshot, config: &BoardConfigRes) {
+    let save_file = SaveFile {
+        version: SAVE_VERSION,
+        config: *config,
+        board: board.clone(),
+    };
+    match ron::ser::to_string_pretty(&save_file, ron::ser::PrettyConfig::default()) {
+        Ok(text) => {
+            if let Err(err) = fs::write(SAVE_PATH, text) {
+                error!("Could not write save file: {}", err);
+            } else {
+                info!("Saved turn {}", board.turn.0);
+            }
+        }
+        Err(err) => error!("Could not serialize save file: {}", err),
+    }
+}
+
+pub fn remove_save_file() {
+    if let Err(err) = fs::remove_file(SAVE_PATH) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            error!("Could not remove save file: {}", err);
+        }
+    }
+}
+
+pub fn capture_snapshot(
+    mode: &GameModeRes,
+    turn: &TurnRes,
+    rng: &RngRes,
+    fire_laser: &FireLaserRes,
+    player_transform: &Transform,
+    query_elements: &Query<(
+        &AnimationMoveDown,
+        Option<&Block>,
+        Option<&PowerupAddLaser>,
+        Option<&Bounce>,
+        Option<&Live>,
+        Option<&SpawnLive>,
+    )>,
+) -> BoardSnapshot {
+    let elements = query_elements
+        .iter()
+        .filter_map(
+            |(animation_move_down, block, powerup_laser, bounce, live, spawn_live)| {
+                element::element_kind(block, powerup_laser, bounce).map(|kind| ElementSnapshot {
+                    kind,
+                    x: animation_move_down.destination.x,
+                    y: animation_move_down.destination.y,
+                    live: live.map(|live| live.0),
+                    spawn_live: spawn_live.map(|spawn_live| spawn_live.0),
+                })
+            },
+        )
+        .collect();
+
+    BoardSnapshot {
+        mode: mode.0,
+        turn: *turn,
+        rng: rng.clone(),
+        fire_laser: fire_laser.clone(),
+        player_x: player_transform.translation.x,
+        elements,
+    }
+}
+
+///Replaces the current board with the snapshot. The game state has to be changed by the caller.
+#[allow(clippy::too_many_arguments)]
+pub fn restore_snapshot(
+    snapshot: &BoardSnapshot,
+    commands: &mut Commands,
+    theme_materials: &ThemeMaterialsRes,
+    query_elements: &Query<Entity, With<AnimationMoveDown>>,
+    player_transform: &mut Transform,
+    mode: &mut GameModeRes,
+    turn: &mut TurnRes,
+    rng: &mut RngRes,
+    fire_laser: &mut FireLaserRes,
+    config: &BoardConfigRes,
+) {
+    for entity in query_elements.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for element in snapshot.elements.iter() {
+        element::spawn_element(
+            commands,
+            theme_materials,
+            element.kind,
+            Vec3::new(element.x, element.y, 0.0),
+            element.live.zip(element.spawn_live),
+            config,
+        );
+    }
+    player_transform.translation.x = snapshot.player_x;
+    mode.0 = snapshot.mode;
+    *turn = snapshot.turn;
+    *rng = snapshot.rng.clone();
+    *fire_laser = snapshot.fire_laser.clone();
+}
+
+fn load_save_startup_system(
+    mut save: ResMut<SaveRes>,
+    replay: Res<ReplayRes>,
+    config: Res<BoardConfigRes>,
+) {
+    if replay.is_playback() {
+        return;
+    }
+    if let Some(board) = read_save_file(&config) {
+        info!("Found saved game at turn {}", board.turn.0);
+        save.loaded = Some(board);
+    }
+}
+
+fn snapshot_system(
+    mut save: ResMut<SaveRes>,
+    mode: Res<GameModeRes>,
+    turn: Res<TurnRes>,
+    rng: Res<RngRes>,
+    fire_laser: Res<FireLaserRes>,
+    query_player: Query<&Transform, With<Player>>,
+    query_elements: Query<(
+        &AnimationMoveDown,
+        Option<&Block>,
+        Option<&PowerupAddLaser>,
+        Option<&Bounce>,
+        Option<&Live>,
+        Option<&SpawnLive>,
+    )>,
+) {
+    save.snapshot = Some(capture_snapshot(
+        &mode,
+        &turn,
+        &rng,
+        &fire_laser,
+        query_player.single(),
+        &query_elements,
+    ));
+}
+
+///A finished run can not be resumed
+fn remove_save_system(mut save: ResMut<SaveRes>) {
+    save.snapshot = None;
+    save.loaded = None;
+    remove_save_file();
+}
+
+fn save_on_exit_system(
+    save: Res<SaveRes>,
+    replay: Res<ReplayRes>,
+    config: Res<BoardConfigRes>,
+    mut app_exit_events: EventReader<AppExit>,
+) {
+    if app_exit_events.iter().next().is_some() && !replay.is_playback() {
+        if let Some(board) = save.snapshot.as_ref() {
+            write_save_file(board, &config);
+        }
+    }
+}
+
+#[cfg(test)]
+fn save_file_text(version: u32, config: BoardConfigRes, rng: RngRes) -> String {
+    let mut board = BoardSnapshot::new_game(GameMode::Classic, rng, &config);
+    board.turn = TurnRes(7);
+    board.elements.push(ElementSnapshot {
+        kind: ElementKind::Block,
+        x: 1.0,
+        y: 2.0,
+        live: Some(3),
+        spawn_live: Some(7),
+    });
+    let save_file = SaveFile {
+        version,
+        config,
+        board,
+    };
+    ron::ser::to_string_pretty(&save_file, ron::ser::PrettyConfig::default()).unwrap()
+}
+
+#[test]
+fn test_save_file_round_trip() {
+    use rand::RngCore;
+
+    let config = BoardConfigRes::default();
+    let mut rng = RngRes::from_seed(42);
+    rng.rng().next_u64();
+    let text = save_file_text(SAVE_VERSION, config, rng.clone());
+
+    let mut board = parse_save_file(&text, &config).unwrap();
+    assert_eq!(board.turn.0, 7);
+    assert_eq!(board.elements.len(), 1);
+    assert_eq!(board.elements[0].live, Some(3));
+    assert_eq!(board.elements[0].spawn_live, Some(7));
+    assert_eq!(board.rng.seed(), 42);
+    for _ in 0..10 {
+        assert_eq!(board.rng.rng().next_u64(), rng.rng().next_u64());
+    }
+}
+
+#[test]
+fn test_reject_save_file() {
+    let config = BoardConfigRes::default();
+    let text = save_file_text(SAVE_VERSION - 1, config, RngRes::from_seed(42));
+    assert!(parse_save_file(&text, &config).is_err());
+
+    let other_config = BoardConfigRes {
+        columns: config.columns + 1,
+        ..config
+    };
+    let text = save_file_text(SAVE_VERSION, other_config, RngRes::from_seed(42));
+    assert!(parse_save_file(&text, &config).is_err());
+    assert!(parse_save_file(&text, &other_config).is_ok());
+}
@@ -0,0 +1,237 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::BindingsRes,
+    laser::AIMING_LASERS,
+    player::AimSettingsRes,
+    theme::ThemeRes,
+    window::{DisplaySettingsRes, FRAME_RATE_OPTIONS},
+};
+
+///Bump when `Settings` changes, a file of another version falls back to the default settings
+pub const SETTINGS_VERSION: u32 = 3;
+pub const SETTINGS_PATH: &str = "settings.ron";
+///Copy of a settings file, that could not be loaded and will be written over
+pub const SETTINGS_BACKUP_PATH: &str = "settings.ron.bak";
+
+///Choices for `GameSettingsRes::aiming_preview`
+pub const AIMING_PREVIEW_OPTIONS: [usize; 4] = [2, 4, 8, AIMING_LASERS];
+///Choices for `GameSettingsRes::volume`
+pub const VOLUME_OPTIONS: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+///Loads all settings from `SETTINGS_PATH` at startup and writes them, whenever one of them changes.
+///Has to be added before the plugins using the settings, they keep the loaded values.
+pub struct SettingsPlugin;
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        //Runs before `LogPlugin`, errors are logged by `report_settings_error_system`
+        let (settings, error) = match read_settings_file() {
+            Ok(settings) => (settings.unwrap_or_default(), None),
+            Err(err) => (Settings::default(), Some(backup_settings_file(err))),
+        };
+        let settings = settings.validate();
+        app.insert_resource(SettingsErrorRes(error))
+            .insert_resource(settings.bindings)
+            .insert_resource(settings.aim)
+            .insert_resource(settings.display)
+            .insert_resource(settings.game)
+            .insert_resource(settings.theme)
+            .add_startup_system(report_settings_error_system)
+            .add_system_to_stage(CoreStage::Last, write_settings_system);
+    }
+}
+
+///Why the settings file could not be loaded at startup
+pub struct SettingsErrorRes(pub Option<String>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    ///Random values above it spawn a block, lower values spawn more blocks
+    pub fn block_probability(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.45,
+            Difficulty::Normal => 0.3,
+            Difficulty::Hard => 0.15,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameSettingsRes {
+    pub difficulty: Difficulty,
+    ///Laser segments of the aiming preview, every bounce starts a new one
+    pub aiming_preview: usize,
+    ///Master volume from 0 to 1, for the sounds to come
+    pub volume: f32,
+}
+
+impl Default for GameSettingsRes {
+    fn default() -> Self {
+        Self {
+            difficulty: Difficulty::Normal,
+            aiming_preview: AIMING_LASERS,
+            volume: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    pub bindings: BindingsRes,
+    pub aim: AimSettingsRes,
+    pub display: DisplaySettingsRes,
+    pub game: GameSettingsRes,
+    pub theme: ThemeRes,
+}
+
+impl Settings {
+    ///Replaces values the game can not work with
+    pub fn validate(mut self) -> Self {
+        if self.aim.snap_steps == Some(0) {
+            self.aim.snap_steps = None;
+        }
+        self.aim.fine_aim_sensitivity = self.aim.fine_aim_sensitivity.clamp(0.01, 1.0);
+        if !FRAME_RATE_OPTIONS.contains(&self.display.frame_rate) {
+            self.display.frame_rate = None;
+        }
+        self.game.aiming_preview = self.game.aiming_preview.clamp(1, AIMING_LASERS);
+        self.game.volume = if self.game.volume.is_nan() {
+            GameSettingsRes::default().volume
+        } else {
+            self.game.volume.clamp(0.0, 1.0)
+        };
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SettingsFile {
+    version: u32,
+    settings: Settings,
+}
+
+///`Ok(None)` without a settings file
+pub fn read_settings_file() -> Result<Option<Settings>, String> {
+    match fs::read_to_string(SETTINGS_PATH) {
+        Ok(text) => parse_settings(&text).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_settings(text: &str) -> Result<Settings, String> {
+    match ron::from_str::<SettingsFile>(text) {
+        Ok(file) if file.version == SETTINGS_VERSION => Ok(file.settings),
+        Ok(file) => Err(format!(
+            "Ignore settings file with version {} (expected {})",
+            file.version, SETTINGS_VERSION
+        )),
+        Err(err) => Err(format!("Could not read settings file: {}", err)),
+    }
+}
+
+///Keeps the settings file, that could not be loaded, before it is written over
+fn backup_settings_file(err: String) -> String {
+    match fs::copy(SETTINGS_PATH, SETTINGS_BACKUP_PATH) {
+        Ok(_) => format!("{}, it is kept as {}", err, SETTINGS_BACKUP_PATH),
+        Err(backup_err) => format!(
+            "{}, it will be written over, could not back it up: {}",
+            err, backup_err
+        ),
+    }
+}
+
+pub fn write_settings_file(settings: &Settings) {
+    let file = SettingsFile {
+        version: SETTINGS_VERSION,
+        settings: settings.clone(),
+    };
+    match ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default()) {
+        Ok(text) => {
+            if let Err(err) = fs::write(SETTINGS_PATH, text) {
+                error!("Could not write settings file: {}", err);
+            }
+        }
+        Err(err) => error!("Could not serialize settings file: {}", err),
+    }
+}
+
+///Whether the resource was changed after it was loaded
+fn is_modified<T: Send + Sync + 'static>(res: &Res<T>) -> bool {
+    res.is_changed() && !res.is_added()
+}
+
+fn report_settings_error_system(error: Res<SettingsErrorRes>) {
+    if let Some(err) = &error.0 {
+        warn!("{}", err);
+    }
+}
+
+fn write_settings_system(
+    bindings: Res<BindingsRes>,
+    aim: Res<AimSettingsRes>,
+    display: Res<DisplaySettingsRes>,
+    game: Res<GameSettingsRes>,
+    theme: Res<ThemeRes>,
+) {
+    if is_modified(&bindings)
+        || is_modified(&aim)
+        || is_modified(&display)
+        || is_modified(&game)
+        || is_modified(&theme)
+    {
+        write_settings_file(&Settings {
+            bindings: bindings.clone(),
+            aim: aim.clone(),
+            display: *display,
+            game: *game,
+            theme: *theme,
+        });
+    }
+}
+
+#[test]
+fn test_validate_settings() {
+    let mut settings = Settings::default();
+    settings.aim.snap_steps = Some(0);
+    settings.aim.fine_aim_sensitivity = 5.0;
+    settings.display.frame_rate = Some(0);
+    settings.game.aiming_preview = 0;
+    settings.game.volume = 2.0;
+
+    let settings = settings.validate();
+    assert_eq!(settings.aim.snap_steps, None);
+    assert_eq!(settings.aim.fine_aim_sensitivity, 1.0);
+    assert_eq!(settings.display.frame_rate, None);
+    assert_eq!(settings.game.aiming_preview, 1);
+    assert_eq!(settings.game.volume, 1.0);
+
+    let default = GameSettingsRes::default();
+    assert_eq!(Settings::default().validate().game, default);
+}
+
+#[test]
+fn test_parse_settings() {
+    let text = |version| {
+        ron::to_string(&SettingsFile {
+            version,
+            settings: Settings::default(),
+        })
+        .unwrap()
+    };
+    assert!(parse_settings(&text(SETTINGS_VERSION)).is_ok());
+    assert!(parse_settings(&text(SETTINGS_VERSION + 1))
+        .unwrap_err()
+        .contains("version"));
+    assert!(parse_settings("not ron").is_err());
+}
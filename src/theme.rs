@@ -0,0 +1,325 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+///Materials between `Palette::block_damaged` and `Palette::block`
+pub const BLOCK_GRADIENT_STEPS: usize = 8;
+
+///Shared materials of the game world, they are recoloured when `ThemeRes` changes.
+///Has to be added after the `DefaultPlugins` and before the plugins spawning with the materials.
+pub struct ThemePlugin;
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ThemeRes>()
+            .init_resource::<ThemeMaterialsRes>()
+            .add_system(recolor_system);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    Dark,
+    Light,
+    ///Safe for deuteranopia and protanopia, distinguishes by blue, orange and yellow instead of red and green
+    ColorBlind,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::ColorBlind];
+
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Dark => Palette {
+                background: Color::rgb(0.1, 0.1, 0.1),
+                letterbox: Color::BLACK,
+                text: Color::WHITE,
+                menu_background: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                button: Color::rgb(0.15, 0.15, 0.15),
+                button_hovered: Color::rgb(0.25, 0.25, 0.25),
+                button_pressed: Color::rgb(0.35, 0.75, 0.35),
+                block: Color::rgb(0.0, 0.0, 1.0),
+                block_damaged: Color::rgb(0.55, 0.75, 1.0),
+                block_inner: Color::rgb(0.0, 0.0, 0.0),
+                powerup_laser: Color::rgb(0.0, 1.0, 0.0),
+                aiming_laser: Color::rgb(1.0, 0.5, 0.0),
+                fire_lasers: [Color::rgb(0.9, 0.1, 0.0), Color::rgb(0.8, 0.2, 1.0)],
+                player: Color::rgb(1.0, 0.0, 0.0),
+                cannon: Color::rgb(0.0, 0.0, 1.0),
+                position: Color::rgb(0.2, 0.2, 0.2),
+                position_hovered: Color::rgb(0.2, 0.2, 0.4),
+                position_selected: Color::rgb(0.2, 0.4, 0.2),
+                position_selected_hovered: Color::rgb(0.4, 0.2, 0.2),
+            },
+            Theme::Light => Palette {
+                background: Color::rgb(0.9, 0.9, 0.9),
+                letterbox: Color::rgb(0.6, 0.6, 0.6),
+                text: Color::BLACK,
+                menu_background: Color::rgba(1.0, 1.0, 1.0, 0.6),
+                button: Color::rgb(0.8, 0.8, 0.8),
+                button_hovered: Color::rgb(0.7, 0.7, 0.7),
+                button_pressed: Color::rgb(0.55, 0.85, 0.55),
+                block: Color::rgb(0.2, 0.3, 0.9),
+                block_damaged: Color::rgb(0.7, 0.75, 0.95),
+                block_inner: Color::rgb(0.97, 0.97, 0.97),
+                powerup_laser: Color::rgb(0.1, 0.7, 0.1),
+                aiming_laser: Color::rgb(0.95, 0.45, 0.0),
+                fire_lasers: [Color::rgb(0.85, 0.1, 0.0), Color::rgb(0.6, 0.1, 0.8)],
+                player: Color::rgb(0.85, 0.1, 0.1),
+                cannon: Color::rgb(0.2, 0.3, 0.9),
+                position: Color::rgb(0.75, 0.75, 0.75),
+                position_hovered: Color::rgb(0.7, 0.7, 0.9),
+                position_selected: Color::rgb(0.7, 0.9, 0.7),
+                position_selected_hovered: Color::rgb(0.9, 0.7, 0.7),
+            },
+            //Okabe-Ito colours
+            Theme::ColorBlind => Palette {
+                background: Color::rgb(0.1, 0.1, 0.1),
+                letterbox: Color::BLACK,
+                text: Color::WHITE,
+                menu_background: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                button: Color::rgb(0.15, 0.15, 0.15),
+                button_hovered: Color::rgb(0.25, 0.25, 0.25),
+                button_pressed: Color::rgb(0.0, 0.45, 0.7),
+                block: Color::rgb(0.0, 0.45, 0.7),
+                block_damaged: Color::rgb(0.8, 0.47, 0.65),
+                block_inner: Color::rgb(0.0, 0.0, 0.0),
+                powerup_laser: Color::rgb(0.94, 0.89, 0.26),
+                aiming_laser: Color::rgb(0.9, 0.6, 0.0),
+                fire_lasers: [Color::rgb(0.9, 0.6, 0.0), Color::rgb(0.34, 0.71, 0.91)],
+                player: Color::rgb(0.84, 0.37, 0.0),
+                cannon: Color::rgb(0.34, 0.71, 0.91),
+                position: Color::rgb(0.2, 0.2, 0.2),
+                position_hovered: Color::rgb(0.15, 0.3, 0.45),
+                position_selected: Color::rgb(0.45, 0.42, 0.1),
+                position_selected_hovered: Color::rgb(0.5, 0.28, 0.05),
+            },
+        }
+    }
+}
+
+///Colours of a theme
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub background: Color,
+    ///Bars around the board
+    pub letterbox: Color,
+    pub text: Color,
+    ///Behind the menu screens and the pause button
+    pub menu_background: Color,
+    pub button: Color,
+    pub button_hovered: Color,
+    pub button_pressed: Color,
+    ///Block with all the lives it was spawned with
+    pub block: Color,
+    ///Block without lives left
+    pub block_damaged: Color,
+    pub block_inner: Color,
+    pub powerup_laser: Color,
+    pub aiming_laser: Color,
+    ///Fired lasers alternate between the colours
+    pub fire_lasers: [Color; 2],
+    pub player: Color,
+    pub cannon: Color,
+    pub position: Color,
+    pub position_hovered: Color,
+    pub position_selected: Color,
+    pub position_selected_hovered: Color,
+}
+
+impl Palette {
+    ///Colour of the gradient step, from `block_damaged` at 0 to `block` at the last step
+    pub fn block_gradient(&self, step: usize) -> Color {
+        let t = step as f32 / (BLOCK_GRADIENT_STEPS - 1) as f32;
+        let damaged = Vec4::from(self.block_damaged.as_rgba_f32());
+        let block = Vec4::from(self.block.as_rgba_f32());
+        let color = damaged.lerp(block, t);
+        Color::rgba(color.x, color.y, color.z, color.w)
+    }
+}
+
+///Text coloured with `Palette::text`, recoloured when the theme changes
+#[derive(Component)]
+pub struct ThemedText;
+
+///Selected theme, stored in the settings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThemeRes(pub Theme);
+
+impl Default for ThemeRes {
+    fn default() -> Self {
+        Self(Theme::Dark)
+    }
+}
+
+///Elements, lasers, the player and the menus use these materials instead of adding their own
+pub struct ThemeMaterialsRes {
+    pub font: Handle<Font>,
+    ///Colour of new texts, they should be marked with `ThemedText` to follow changes
+    pub text: Color,
+    pub letterbox: Handle<ColorMaterial>,
+    pub menu_background: Handle<ColorMaterial>,
+    pub button: Handle<ColorMaterial>,
+    pub button_hovered: Handle<ColorMaterial>,
+    pub button_pressed: Handle<ColorMaterial>,
+    ///See `ThemeMaterialsRes::block`
+    pub block_gradient: Vec<Handle<ColorMaterial>>,
+    pub block_inner: Handle<ColorMaterial>,
+    pub powerup_laser: Handle<ColorMaterial>,
+    pub aiming_laser: Handle<ColorMaterial>,
+    pub fire_lasers: [Handle<ColorMaterial>; 2],
+    pub player: Handle<ColorMaterial>,
+    pub cannon: Handle<ColorMaterial>,
+    pub position: Handle<ColorMaterial>,
+    pub position_hovered: Handle<ColorMaterial>,
+    pub position_selected: Handle<ColorMaterial>,
+    pub position_selected_hovered: Handle<ColorMaterial>,
+}
+
+impl ThemeMaterialsRes {
+    ///`add` creates the material of a colour
+    fn new(
+        palette: &Palette,
+        font: Handle<Font>,
+        mut add: impl FnMut(Color) -> Handle<ColorMaterial>,
+    ) -> Self {
+        Self {
+            font,
+            text: palette.text,
+            letterbox: add(palette.letterbox),
+            menu_background: add(palette.menu_background),
+            button: add(palette.button),
+            button_hovered: add(palette.button_hovered),
+            button_pressed: add(palette.button_pressed),
+            block_gradient: (0..BLOCK_GRADIENT_STEPS)
+                .map(|step| add(palette.block_gradient(step)))
+                .collect(),
+            block_inner: add(palette.block_inner),
+            powerup_laser: add(palette.powerup_laser),
+            aiming_laser: add(palette.aiming_laser),
+            fire_lasers: [add(palette.fire_lasers[0]), add(palette.fire_lasers[1])],
+            player: add(palette.player),
+            cannon: add(palette.cannon),
+            position: add(palette.position),
+            position_hovered: add(palette.position_hovered),
+            position_selected: add(palette.position_selected),
+            position_selected_hovered: add(palette.position_selected_hovered),
+        }
+    }
+
+    ///Materials without assets, for tests spawning elements
+    #[cfg(test)]
+    pub fn without_assets() -> Self {
+        Self::new(&Theme::Dark.palette(), Handle::default(), |_| {
+            Handle::default()
+        })
+    }
+
+    ///Material of a block with the share `lives_left` of its spawn lives
+    pub fn block(&self, lives_left: f32) -> &Handle<ColorMaterial> {
+        let step = (lives_left.clamp(0.0, 1.0) * (BLOCK_GRADIENT_STEPS - 1) as f32).round();
+        &self.block_gradient[step as usize]
+    }
+
+    ///Sets the colours of all materials to the palette
+    fn recolor(&mut self, materials: &mut Assets<ColorMaterial>, palette: &Palette) {
+        self.text = palette.text;
+        let colors = [
+            (&self.letterbox, palette.letterbox),
+            (&self.menu_background, palette.menu_background),
+            (&self.button, palette.button),
+            (&self.button_hovered, palette.button_hovered),
+            (&self.button_pressed, palette.button_pressed),
+            (&self.block_inner, palette.block_inner),
+            (&self.powerup_laser, palette.powerup_laser),
+            (&self.aiming_laser, palette.aiming_laser),
+            (&self.fire_lasers[0], palette.fire_lasers[0]),
+            (&self.fire_lasers[1], palette.fire_lasers[1]),
+            (&self.player, palette.player),
+            (&self.cannon, palette.cannon),
+            (&self.position, palette.position),
+            (&self.position_hovered, palette.position_hovered),
+            (&self.position_selected, palette.position_selected),
+            (
+                &self.position_selected_hovered,
+                palette.position_selected_hovered,
+            ),
+        ];
+        let gradient = self
+            .block_gradient
+            .iter()
+            .enumerate()
+            .map(|(step, material)| (material, palette.block_gradient(step)));
+        for (material, color) in colors.into_iter().chain(gradient) {
+            if let Some(material) = materials.get_mut(material) {
+                material.color = color;
+            }
+        }
+    }
+}
+
+impl FromWorld for ThemeMaterialsRes {
+    fn from_world(world: &mut World) -> Self {
+        let palette = world.get_resource::<ThemeRes>().unwrap().0.palette();
+        let font = world
+            .get_resource::<AssetServer>()
+            .unwrap()
+            .load("fonts/DejaVuSans.ttf");
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        Self::new(&palette, font, |color| materials.add(color.into()))
+    }
+}
+
+///Also runs on the first frame, so the clear colour starts with the stored theme
+fn recolor_system(
+    theme: Res<ThemeRes>,
+    mut theme_materials: ResMut<ThemeMaterialsRes>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut clear_color: ResMut<ClearColor>,
+    mut query_text: Query<&mut Text, With<ThemedText>>,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+    let palette = theme.0.palette();
+    theme_materials.recolor(&mut materials, &palette);
+    clear_color.0 = palette.background;
+    for mut text in query_text.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.color = palette.text;
+        }
+    }
+}
+
+#[test]
+fn test_palettes_distinguishable() {
+    for theme in Theme::ALL {
+        let palette = theme.palette();
+        assert_ne!(
+            palette.fire_lasers[0], palette.fire_lasers[1],
+            "{:?}",
+            theme
+        );
+        let positions = [
+            palette.position,
+            palette.position_hovered,
+            palette.position_selected,
+            palette.position_selected_hovered,
+        ];
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                assert_ne!(a, b, "{:?}", theme);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_block_gradient() {
+    let palette = Theme::Dark.palette();
+    assert_eq!(palette.block_gradient(0), palette.block_damaged);
+    let last = Vec4::from(
+        palette
+            .block_gradient(BLOCK_GRADIENT_STEPS - 1)
+            .as_rgba_f32(),
+    );
+    assert!(last.abs_diff_eq(Vec4::from(palette.block.as_rgba_f32()), 1e-6));
+}
@@ -1,4 +1,45 @@
-//use bevy::prelude::*;
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
 
 pub const FRAME_RATE: f64 = 1.0 / 60.0;
-pub const LASER_RATE: f64 = 5.0 / 60.0;
+pub const LASER_RATE: f64 = 1.0 / 24.0;
+
+///Speeds up all animations and the fire laser timestep, e.g. for replay playback.
+pub struct TimeScaleRes(pub f32);
+
+impl Default for TimeScaleRes {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl TimeScaleRes {
+    pub fn delta_seconds(&self, time: &Time) -> f32 {
+        time.delta_seconds() * self.0
+    }
+}
+
+#[derive(Default)]
+pub struct LaserTimestepState {
+    accumulator: f64,
+    looping: bool,
+}
+
+///Like `FixedTimestep::step(LASER_RATE)`, but scaled by `TimeScaleRes`.
+pub fn laser_timestep(
+    mut state: Local<LaserTimestepState>,
+    time: Res<Time>,
+    time_scale: Res<TimeScaleRes>,
+) -> ShouldRun {
+    if !state.looping {
+        state.accumulator += time.delta_seconds_f64() * time_scale.0 as f64;
+    }
+
+    if state.accumulator >= LASER_RATE {
+        state.accumulator -= LASER_RATE;
+        state.looping = true;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        state.looping = false;
+        ShouldRun::No
+    }
+}
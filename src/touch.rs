@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use bevy::{
+    input::{
+        touch::{Touch, Touches},
+        InputSystem,
+    },
+    prelude::*,
+    render::camera::OrthographicProjection,
+};
+
+use crate::{
+    action::{Action, ActionLabel, ActionsRes, RebindRes},
+    click::{self, ClickEvent, Clickable},
+    window::window_to_world,
+    MainCamera,
+};
+
+///Touches moved less than this are taps, longer ones are slingshot drags
+pub const TAP_DISTANCE: f32 = 10.0;
+
+///Touches are dragged back like a slingshot to aim, releasing fires. A tap clicks a `Clickable`.
+pub struct TouchPlugin;
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TouchStartsRes::default())
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                track_touch_starts_system
+                    .label(TouchLabel::TrackStarts)
+                    .after(InputSystem),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                touch_actions_system
+                    .after(TouchLabel::TrackStarts)
+                    .after(ActionLabel::Update),
+            )
+            .add_system(touch_tap_system);
+    }
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub enum TouchLabel {
+    TrackStarts,
+}
+
+///Start position of every pressed or just released touch.
+///Released touches in `Touches` do not keep their start position.
+#[derive(Default)]
+pub struct TouchStartsRes(HashMap<u64, Vec2>);
+
+impl TouchStartsRes {
+    ///Slingshot direction of the touch, `None` for a tap
+    pub fn gesture(&self, touch: &Touch) -> Option<Vec2> {
+        self.0
+            .get(&touch.id())
+            .and_then(|start| slingshot_direction(*start, touch.position()))
+    }
+}
+
+///Aiming direction of a touch pulled back from its start. `None`, as long as it is a tap.
+pub fn slingshot_direction(start: Vec2, position: Vec2) -> Option<Vec2> {
+    let pull = start - position;
+    if pull.length() < TAP_DISTANCE {
+        None
+    } else {
+        Some(pull)
+    }
+}
+
+fn track_touch_starts_system(mut touch_starts: ResMut<TouchStartsRes>, touches: Res<Touches>) {
+    touch_starts
+        .0
+        .retain(|id, _| touches.get_pressed(*id).is_some() || touches.just_released(*id));
+    for touch in touches.iter_just_pressed() {
+        touch_starts.0.insert(touch.id(), touch.start_position());
+    }
+}
+
+fn touch_actions_system(
+    mut actions: ResMut<ActionsRes>,
+    rebind: Res<RebindRes>,
+    touches: Res<Touches>,
+    touch_starts: Res<TouchStartsRes>,
+) {
+    if rebind.0.is_some() {
+        return;
+    }
+    if let Some(direction) = touches.iter().find_map(|touch| touch_starts.gesture(touch)) {
+        actions.aim = Some(direction);
+    }
+    if let Some(direction) = touches
+        .iter_just_released()
+        .find_map(|touch| touch_starts.gesture(touch))
+    {
+        actions.aim = Some(direction);
+        actions.press(Action::Fire);
+    }
+}
+
+fn touch_tap_system(
+    mut click_events: EventWriter<ClickEvent>,
+    touches: Res<Touches>,
+    touch_starts: Res<TouchStartsRes>,
+    query: Query<(Entity, &GlobalTransform, &Clickable)>,
+    query_camera: Query<(&GlobalTransform, &OrthographicProjection), With<MainCamera>>,
+) {
+    let (camera_transform, orthographic_projection) = match query_camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+
+    for touch in touches
+        .iter_just_released()
+        .filter(|touch| touch_starts.gesture(touch).is_none())
+    {
+        let position = window_to_world(touch.position(), camera_transform, orthographic_projection);
+        //Like a mouse click, the topmost clickable blocks the ones below, even if it ignores taps
+        if let Some((entity, _, clickable)) =
+            click::topmost_at(query.iter(), position).and_then(|entity| query.get(entity).ok())
+        {
+            if clickable.buttons.contains(&Action::Select) {
+                click_events.send(ClickEvent {
+                    entity,
+                    button: Action::Select,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn send_touch(world: &mut World, phase: bevy::input::touch::TouchPhase, position: Vec2) {
+    use bevy::{app::Events, input::touch::TouchInput};
+
+    world
+        .get_resource_mut::<Events<TouchInput>>()
+        .unwrap()
+        .send(TouchInput {
+            phase,
+            position,
+            force: None,
+            id: 0,
+        });
+}
+
+#[cfg(test)]
+fn touch_test_world() -> (World, SystemStage) {
+    use bevy::{
+        app::Events,
+        input::touch::{touch_screen_input_system, TouchInput},
+    };
+
+    let mut world = World::default();
+    world.insert_resource(ActionsRes::default());
+    world.insert_resource(RebindRes::default());
+    world.insert_resource(Touches::default());
+    world.insert_resource(TouchStartsRes::default());
+    world.insert_resource(Events::<TouchInput>::default());
+    world.insert_resource(Events::<ClickEvent>::default());
+
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(touch_screen_input_system.label(InputSystem));
+    stage.add_system(
+        track_touch_starts_system
+            .label(TouchLabel::TrackStarts)
+            .after(InputSystem),
+    );
+    stage.add_system(touch_actions_system.after(TouchLabel::TrackStarts));
+    stage.add_system(touch_tap_system.after(TouchLabel::TrackStarts));
+    (world, stage)
+}
+
+#[test]
+fn test_touch_slingshot() {
+    use bevy::{ecs::schedule::Stage, input::touch::TouchPhase};
+
+    let (mut world, mut stage) = touch_test_world();
+
+    send_touch(&mut world, TouchPhase::Started, Vec2::new(100.0, 100.0));
+    stage.run(&mut world);
+    send_touch(&mut world, TouchPhase::Moved, Vec2::new(100.0, 50.0));
+    stage.run(&mut world);
+    let actions = world.get_resource::<ActionsRes>().unwrap();
+    assert_eq!(actions.aim, Some(Vec2::new(0.0, 50.0)));
+    assert!(!actions.pressed(Action::Fire));
+
+    send_touch(&mut world, TouchPhase::Ended, Vec2::new(80.0, 50.0));
+    stage.run(&mut world);
+    let actions = world.get_resource::<ActionsRes>().unwrap();
+    assert_eq!(actions.aim, Some(Vec2::new(20.0, 50.0)));
+    assert!(actions.just_pressed(Action::Fire));
+}
+
+#[test]
+fn test_touch_tap() {
+    use bevy::{app::Events, ecs::schedule::Stage, input::touch::TouchPhase};
+
+    let (mut world, mut stage) = touch_test_world();
+    world
+        .spawn()
+        .insert(MainCamera)
+        .insert(GlobalTransform::default())
+        .insert(OrthographicProjection {
+            left: -100.0,
+            bottom: -100.0,
+            ..Default::default()
+        });
+    let clickable = world
+        .spawn()
+        .insert(GlobalTransform::from_xyz(20.0, 20.0, 0.0))
+        .insert(Clickable::new(click::ClickShape::Rect(Vec2::new(
+            10.0, 10.0,
+        ))))
+        .id();
+
+    send_touch(&mut world, TouchPhase::Started, Vec2::new(122.0, 118.0));
+    stage.run(&mut world);
+    send_touch(&mut world, TouchPhase::Ended, Vec2::new(122.0, 120.0));
+    stage.run(&mut world);
+
+    let click_events = world.get_resource::<Events<ClickEvent>>().unwrap();
+    let clicked = click_events
+        .get_reader()
+        .iter(click_events)
+        .map(|event| event.entity)
+        .collect::<Vec<_>>();
+    assert_eq!(clicked, vec![clickable]);
+    assert!(!world
+        .get_resource::<ActionsRes>()
+        .unwrap()
+        .just_pressed(Action::Fire));
+
+    //A clickable for the context button only ignores taps
+    world.get_mut::<Clickable>(clickable).unwrap().buttons = vec![Action::Context];
+    send_touch(&mut world, TouchPhase::Started, Vec2::new(122.0, 118.0));
+    stage.run(&mut world);
+    send_touch(&mut world, TouchPhase::Ended, Vec2::new(122.0, 120.0));
+    stage.run(&mut world);
+    let click_events = world.get_resource::<Events<ClickEvent>>().unwrap();
+    assert_eq!(click_events.get_reader().iter(click_events).count(), 1);
+}
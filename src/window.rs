@@ -1,42 +1,332 @@
-use bevy::{prelude::*, window::WindowResizeConstraints};
+use std::time::{Duration, Instant};
 
-pub const ROWS: f32 = 16.0;
-pub const COLUMNS: f32 = 9.0;
-pub const SIZE_MULTIPLIER: f32 = 20.0;
-pub const SIZE: f32 = SIZE_MULTIPLIER * 2.0;
+use bevy::{
+    math::Vec3Swizzles, prelude::*, render::camera::OrthographicProjection, window::WindowMode,
+};
+use serde::{Deserialize, Serialize};
 
-pub const WIDTH: f32 = COLUMNS * SIZE_MULTIPLIER * 2.0;
-pub const HEIGHT: f32 = ROWS * SIZE_MULTIPLIER * 2.0;
+use crate::{theme::ThemeMaterialsRes, MainCamera};
 
+///Choices for `DisplaySettingsRes::frame_rate`
+pub const FRAME_RATE_OPTIONS: [Option<u32>; 4] = [None, Some(30), Some(60), Some(144)];
+
+///Bars around the board are drawn above the elements
+const LETTERBOX_Z: f32 = 500.0;
+
+///Start with `--board <columns>x<rows>` for another board size, e.g. `--board 7x12`.
 pub struct WindowPlugin;
 impl Plugin for WindowPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(WindowDescriptor {
-            width: WIDTH,
-            height: HEIGHT,
-            resize_constraints: WindowResizeConstraints {
-                min_width: WIDTH,
-                min_height: HEIGHT,
-                max_width: WIDTH,
-                max_height: HEIGHT,
-            },
-            scale_factor_override: None,
-            title: "Bevy Laser".to_string(),
+        //Runs before `LogPlugin`, errors are logged by `report_board_error_system`
+        let (board, error) = match BoardConfigRes::from_args(std::env::args().collect()) {
+            Ok(board) => (board, None),
+            Err(err) => (BoardConfigRes::default(), Some(err)),
+        };
+        //Loaded by `SettingsPlugin`
+        let display = *app
+            .world
+            .get_resource_or_insert_with(DisplaySettingsRes::default);
+        app.insert_resource(board)
+            .insert_resource(BoardErrorRes(error))
+            .insert_resource(WindowDescriptor {
+                width: board.width(),
+                height: board.height(),
+                scale_factor_override: None,
+                title: "Bevy Laser".to_string(),
+                vsync: display.vsync,
+                resizable: true,
+                decorations: true,
+                cursor_visible: true,
+                cursor_locked: false,
+                mode: display.mode.window_mode(),
+                ..Default::default()
+            })
+            .add_startup_system(spawn_letterbox_system)
+            .add_startup_system(report_board_error_system)
+            .add_system(fit_camera_system)
+            .add_system(apply_display_settings_system)
+            .add_system_to_stage(CoreStage::Last, frame_limiter_system);
+    }
+}
+
+///Why the board of `--board` could not be used
+pub struct BoardErrorRes(pub Option<String>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayMode {
+    Windowed,
+    ///Fullscreen window at the desktop resolution
+    Borderless,
+    ///Exclusive fullscreen, changes the resolution of the monitor
+    Fullscreen,
+}
+
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::Windowed,
+        DisplayMode::Borderless,
+        DisplayMode::Fullscreen,
+    ];
+
+    pub fn window_mode(self) -> WindowMode {
+        match self {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen { use_size: false },
+        }
+    }
+}
+
+///Window settings, changes are applied to the window
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DisplaySettingsRes {
+    pub mode: DisplayMode,
+    pub vsync: bool,
+    ///Frames per second the game is limited to, `None` for no limit besides vsync
+    pub frame_rate: Option<u32>,
+}
+
+impl Default for DisplaySettingsRes {
+    fn default() -> Self {
+        Self {
+            mode: DisplayMode::Windowed,
             vsync: true,
-            resizable: true,
-            decorations: true,
-            cursor_visible: true,
-            cursor_locked: false,
-            mode: bevy::window::WindowMode::Windowed,
+            frame_rate: None,
+        }
+    }
+}
+
+///Dimensions of the board, chosen at startup
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoardConfigRes {
+    pub columns: u32,
+    pub rows: u32,
+    ///Half the size of a cell
+    pub size_multiplier: f32,
+}
+
+impl Default for BoardConfigRes {
+    fn default() -> Self {
+        Self {
+            columns: 9,
+            rows: 16,
+            size_multiplier: 20.0,
+        }
+    }
+}
+
+impl BoardConfigRes {
+    ///Smallest board with space for the player positions and the elements above
+    pub const MIN_COLUMNS: u32 = 3;
+    pub const MIN_ROWS: u32 = 5;
+    ///Columns are numbered with `u8` when elements are generated
+    pub const MAX_COLUMNS: u32 = 255;
+
+    ///Board of `--board <columns>x<rows>`, the default board without it
+    fn from_args(args: Vec<String>) -> Result<Self, String> {
+        match args
+            .iter()
+            .position(|arg| arg == "--board")
+            .and_then(|i| args.get(i + 1))
+        {
+            Some(board) => Self::parse(board).ok_or_else(|| {
+                format!(
+                    "Invalid board {}, expected <columns>x<rows> of at least {}x{} and at most {} columns",
+                    board,
+                    Self::MIN_COLUMNS,
+                    Self::MIN_ROWS,
+                    Self::MAX_COLUMNS
+                )
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    ///Parses `<columns>x<rows>`, `None` for a board outside the supported size
+    pub fn parse(board: &str) -> Option<Self> {
+        let (columns, rows) = board.split_once('x')?;
+        let config = Self {
+            columns: columns.parse().ok()?,
+            rows: rows.parse().ok()?,
+            ..Self::default()
+        };
+        (config.columns >= Self::MIN_COLUMNS
+            && config.columns <= Self::MAX_COLUMNS
+            && config.rows >= Self::MIN_ROWS)
+            .then(|| config)
+    }
+
+    ///Size of a cell
+    pub fn size(&self) -> f32 {
+        self.size_multiplier * 2.0
+    }
+
+    pub fn width(&self) -> f32 {
+        self.columns as f32 * self.size()
+    }
+
+    pub fn height(&self) -> f32 {
+        self.rows as f32 * self.size()
+    }
+
+    ///Diagonal of the board, a laser leaves the board within this distance
+    pub fn max_laser_length(&self) -> f32 {
+        Vec2::new(self.width(), self.height()).length()
+    }
+
+    pub fn get_3d_from_cord(&self, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+        (
+            -self.width() / 2.0 + self.size() * (x + 0.5),
+            self.height() / 2.0 - self.size() * (y + 0.5),
+            z,
+        )
+    }
+
+    ///Inverse of `get_3d_from_cord`
+    pub fn get_cord_from_3d(&self, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+        (
+            (x + self.width() / 2.0) / self.size() - 0.5,
+            (self.height() / 2.0 - y) / self.size() - 0.5,
+            z,
+        )
+    }
+
+    ///Center of the board cell at the world position `position`
+    pub fn snap_to_cell(&self, position: Vec2) -> Vec2 {
+        let (x, y, _) = self.get_cord_from_3d(position.x, position.y, 0.0);
+        let (x, y, _) = self.get_3d_from_cord(x.round(), y.round(), 0.0);
+        Vec2::new(x, y)
+    }
+
+    ///Camera scale, that fits the whole board into the window
+    pub fn fit_scale(&self, window_width: f32, window_height: f32) -> f32 {
+        (self.width() / window_width).max(self.height() / window_height)
+    }
+}
+
+///Converts a cursor or touch position of the window into world coordinates of the 2d camera.
+///All window positions go through it, as the camera is scaled to the window size.
+pub fn window_to_world(
+    position: Vec2,
+    camera_transform: &GlobalTransform,
+    orthographic_projection: &OrthographicProjection,
+) -> Vec2 {
+    (position + Vec2::new(orthographic_projection.left, orthographic_projection.bottom))
+        * orthographic_projection.scale
+        + camera_transform.translation.xy()
+}
+
+fn fit_camera_system(
+    board: Res<BoardConfigRes>,
+    windows: Res<Windows>,
+    mut query_camera: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let scale = board.fit_scale(window.width(), window.height());
+    for mut orthographic_projection in query_camera.iter_mut() {
+        //Only set on change, the projection is recalculated for every change
+        if orthographic_projection.scale != scale {
+            orthographic_projection.scale = scale;
+        }
+    }
+}
+
+///The window is created with the stored settings, so only later changes are applied
+fn apply_display_settings_system(display: Res<DisplaySettingsRes>, mut windows: ResMut<Windows>) {
+    if !display.is_changed() || display.is_added() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_mode(display.mode.window_mode());
+        window.set_vsync(display.vsync);
+    }
+}
+
+///Waits at the end of the frame, until the frame took as long as the target frame rate asks for
+fn frame_limiter_system(display: Res<DisplaySettingsRes>, mut frame_start: Local<Option<Instant>>) {
+    if let (Some(frame_rate), Some(start)) = (display.frame_rate, *frame_start) {
+        let frame_time = Duration::from_secs_f64(1.0 / frame_rate as f64);
+        if let Some(remaining) = frame_time.checked_sub(start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+    *frame_start = Some(Instant::now());
+}
+
+fn report_board_error_system(error: Res<BoardErrorRes>) {
+    if let Some(err) = &error.0 {
+        error!("{}", err);
+    }
+}
+
+///Covers the space around the board, when the window has another aspect ratio
+fn spawn_letterbox_system(
+    mut commands: Commands,
+    theme_materials: Res<ThemeMaterialsRes>,
+    board: Res<BoardConfigRes>,
+) {
+    let (width, height) = (board.width(), board.height());
+    //Large enough for any aspect ratio
+    let size = Vec2::new(width, height) * 100.0;
+    let offsets = [
+        Vec2::new(-(width + size.x) / 2.0, 0.0),
+        Vec2::new((width + size.x) / 2.0, 0.0),
+        Vec2::new(0.0, -(height + size.y) / 2.0),
+        Vec2::new(0.0, (height + size.y) / 2.0),
+    ];
+    for offset in offsets {
+        commands.spawn_bundle(SpriteBundle {
+            material: theme_materials.letterbox.clone(),
+            transform: Transform::from_translation(offset.extend(LETTERBOX_Z)),
+            sprite: Sprite::new(size),
             ..Default::default()
         });
     }
 }
 
-pub fn get_3d_from_cord(x: f32, y: f32, z: f32) -> (f32, f32, f32) {
-    (
-        -WIDTH / 2.0 + SIZE * (x + 0.5),
-        HEIGHT / 2.0 - SIZE * (y + 0.5),
-        z,
-    )
+#[test]
+fn test_window_to_world() {
+    let board = BoardConfigRes::default();
+    let camera_transform = GlobalTransform::from_xyz(10.0, 0.0, 0.0);
+    //Window taller than the board, letterboxed above and below
+    let (window_width, window_height) = (board.width() * 2.0, board.height() * 4.0);
+    let orthographic_projection = OrthographicProjection {
+        left: -window_width / 2.0,
+        right: window_width / 2.0,
+        bottom: -window_height / 2.0,
+        top: window_height / 2.0,
+        scale: board.fit_scale(window_width, window_height),
+        ..Default::default()
+    };
+    assert_eq!(orthographic_projection.scale, 0.5);
+    assert_eq!(
+        window_to_world(
+            Vec2::new(window_width, window_height / 2.0),
+            &camera_transform,
+            &orthographic_projection
+        ),
+        Vec2::new(board.width() / 2.0 + 10.0, 0.0)
+    );
+}
+
+#[test]
+fn test_board_config() {
+    let board = BoardConfigRes::parse("7x12").unwrap();
+    assert_eq!((board.columns, board.rows), (7, 12));
+    assert_eq!(board.get_3d_from_cord(3.0, 5.5, 1.0), (0.0, 0.0, 1.0));
+    assert_eq!(
+        board.get_cord_from_3d(-140.0, 240.0, 0.0),
+        (-0.5, -0.5, 0.0)
+    );
+    assert_eq!(
+        board.snap_to_cell(Vec2::new(-3.0, 50.0)),
+        Vec2::new(0.0, 60.0)
+    );
+    assert_eq!(BoardConfigRes::parse("2x12"), None);
+    assert!(BoardConfigRes::parse("255x12").is_some());
+    assert_eq!(BoardConfigRes::parse("256x12"), None);
+    assert_eq!(BoardConfigRes::parse("7-12"), None);
 }